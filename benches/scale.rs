@@ -0,0 +1,74 @@
+//! Benchmarks for monorepo sized PRs: 10k changed files against a 2k line
+//! CODEOWNERS file.
+use criterion::{Criterion, criterion_group, criterion_main};
+use min_review_bot::{
+    conditional::{self, OwnersConditional, SolveMode},
+    provenance::TrackedConditional,
+    rules::CodeownersRules,
+};
+use std::hint::black_box;
+
+const CODEOWNERS_LINES: usize = 2_000;
+const CHANGED_FILES: usize = 10_000;
+
+/// One rule per service, with every tenth line giving a file extension to a
+/// language team instead.
+fn codeowners() -> String {
+    (0..CODEOWNERS_LINES)
+        .map(|line| {
+            if line % 10 == 0 {
+                format!("*.ext{} @org/lang{}\n", line / 10 % 20, line % 7)
+            } else {
+                format!(
+                    "/services/svc{line}/ @org/team{} @user{}\n",
+                    line % 60,
+                    line % 400
+                )
+            }
+        })
+        .collect()
+}
+
+fn changed_files() -> Vec<String> {
+    (0..CHANGED_FILES)
+        .map(|file| {
+            format!(
+                "services/svc{}/src/mod{file}.ext{}",
+                file % CODEOWNERS_LINES,
+                file % 20
+            )
+        })
+        .collect()
+}
+
+fn bench_scale(c: &mut Criterion) {
+    let codeowners = CodeownersRules::parse(&codeowners());
+    let files = changed_files();
+    let files: Vec<&str> = files.iter().map(|file| file.as_str()).collect();
+    let requirement = OwnersConditional::from_codeowners(&codeowners, &files).reduce();
+
+    let mut group = c.benchmark_group("10k_files_2k_rules");
+    group.sample_size(10);
+    group.bench_function("to_owners_map", |b| {
+        b.iter(|| conditional::to_owners_map(black_box(&codeowners), black_box(&files)))
+    });
+    group.bench_function("from_codeowners_reduce", |b| {
+        b.iter(|| OwnersConditional::from_codeowners(black_box(&codeowners), &files).reduce())
+    });
+    group.bench_function("tracked_from_codeowners_reduce", |b| {
+        b.iter(|| TrackedConditional::from_codeowners(black_box(&codeowners), &files).reduce())
+    });
+    group.bench_function("minimum_owner_sets", |b| {
+        b.iter(|| black_box(&requirement).minimum_owner_sets(SolveMode::Auto))
+    });
+    group.bench_function("cheapest_owner_set", |b| {
+        b.iter(|| {
+            black_box(&requirement)
+                .cheapest_owner_set(SolveMode::Auto, |owner| if owner.is_team() { 3 } else { 1 })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_scale);
+criterion_main!(benches);
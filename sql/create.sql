@@ -2,3 +2,56 @@ CREATE TABLE IF NOT EXISTS last_checked_change (
     pr_id UNSIGNED INTEGER NOT NULL PRIMARY KEY,
     last_updated_unixus INTEGER NOT NULL
 );
+
+CREATE TABLE IF NOT EXISTS team_members (
+    org TEXT NOT NULL,
+    team TEXT NOT NULL,
+    -- Newline separated list of members, e.g. `@login`
+    members TEXT NOT NULL,
+    fetched_unix INTEGER NOT NULL,
+    PRIMARY KEY (org, team)
+);
+
+-- Where CODEOWNERS was last found on each branch, with the ETags GitHub sent for
+-- it and the branch's commit so we can check whether they changed without
+-- downloading them again
+CREATE TABLE IF NOT EXISTS codeowners_refs (
+    git_ref TEXT NOT NULL PRIMARY KEY,
+    path TEXT NOT NULL,
+    etag TEXT,
+    commit_etag TEXT,
+    sha TEXT NOT NULL
+);
+
+-- Content of each CODEOWNERS blob a branch uses, by blob SHA
+CREATE TABLE IF NOT EXISTS codeowners_blobs (
+    sha TEXT NOT NULL PRIMARY KEY,
+    content TEXT NOT NULL
+);
+
+-- The last change in requirements noted on each PR's comment, so the note stays
+-- there until the requirement changes again
+CREATE TABLE IF NOT EXISTS requirement_changes (
+    pr_id UNSIGNED INTEGER NOT NULL PRIMARY KEY,
+    previous TEXT NOT NULL,
+    current TEXT NOT NULL
+);
+
+-- How the CODEOWNERS edits in each PR change file ownership, as JSON, along
+-- with the SHAs of the CODEOWNERS blobs it was worked out from and the head
+-- commit whose files it covers. A side without a CODEOWNERS file has an empty
+-- SHA
+CREATE TABLE IF NOT EXISTS ownership_diffs (
+    pr_id UNSIGNED INTEGER NOT NULL PRIMARY KEY,
+    base_sha TEXT NOT NULL,
+    head_sha TEXT NOT NULL,
+    head_ref TEXT NOT NULL,
+    diff TEXT NOT NULL
+);
+
+-- SHA of the CODEOWNERS blob each PR was last checked against, so PRs are
+-- checked again when their base branch's CODEOWNERS changes
+CREATE TABLE IF NOT EXISTS checked_codeowners (
+    pr_id UNSIGNED INTEGER NOT NULL PRIMARY KEY,
+    sha TEXT NOT NULL
+);
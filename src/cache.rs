@@ -1,15 +1,25 @@
-use crate::config::Config;
+use crate::{
+    config::Config,
+    github::{CodeownersChange, CodeownersLocation},
+    owner::Owner,
+    ownership_diff::OwnershipDiff,
+    rules::CodeownersRules,
+};
 use sqlx::{
     migrate::MigrateDatabase,
     sqlite::{SqlitePool, SqlitePoolOptions},
 };
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    sync::{Arc, Mutex},
     time::{Duration, SystemTime},
 };
 
 pub struct Cache {
     pool: SqlitePool,
+    /// Parsed CODEOWNERS files by blob SHA. These are only kept in memory, as
+    /// they're quick to parse again from the content in the database.
+    parsed_codeowners: Mutex<HashMap<String, Arc<CodeownersRules>>>,
 }
 
 impl Cache {
@@ -24,13 +34,15 @@ impl Cache {
                 .max_connections(10)
                 .connect(db_url)
                 .await?,
+            parsed_codeowners: Mutex::new(HashMap::new()),
         };
         connector.initialise_db().await?;
         Ok(connector)
     }
 
     async fn initialise_db(&self) -> sqlx::Result<()> {
-        sqlx::query_file!("sql/create.sql")
+        // The file has several statements, which prepared queries can't run
+        sqlx::raw_sql(include_str!("../sql/create.sql"))
             .execute(&self.pool)
             .await?;
 
@@ -71,4 +83,295 @@ impl Cache {
 
         Ok(())
     }
+
+    /// SHA of the CODEOWNERS blob each PR was last checked against, by PR ID.
+    pub async fn get_all_checked_codeowners(&self) -> sqlx::Result<BTreeMap<u64, String>> {
+        let query = sqlx::query!("SELECT pr_id, sha FROM checked_codeowners")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(query
+            .into_iter()
+            .map(|row| (row.pr_id.try_into().unwrap(), row.sha))
+            .collect())
+    }
+
+    pub async fn update_checked_codeowners(&self, pr_id: u64, sha: &str) -> sqlx::Result<()> {
+        let pr_id = pr_id as i64;
+        sqlx::query!(
+            "INSERT OR REPLACE INTO checked_codeowners (pr_id, sha) VALUES (?, ?)",
+            pr_id,
+            sha,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns the cached members of `@{org}/{team}`, or `None` if they
+    /// aren't cached or were fetched more than `ttl` ago.
+    pub async fn get_team_members(
+        &self,
+        org: &str,
+        team: &str,
+        ttl: Duration,
+    ) -> sqlx::Result<Option<BTreeSet<Owner>>> {
+        // GitHub org and team names are case insensitive
+        let org = org.to_lowercase();
+        let team = team.to_lowercase();
+        let row = sqlx::query!(
+            "SELECT members, fetched_unix FROM team_members WHERE org = ? AND team = ?",
+            org,
+            team,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.and_then(|row| {
+            let fetched = SystemTime::UNIX_EPOCH
+                + Duration::from_secs(row.fetched_unix.try_into().unwrap_or(0));
+            let age = SystemTime::now()
+                .duration_since(fetched)
+                .unwrap_or(Duration::ZERO);
+            (age < ttl).then(|| row.members.lines().map(Owner::from).collect())
+        }))
+    }
+
+    pub async fn update_team_members(
+        &self,
+        org: &str,
+        team: &str,
+        members: &BTreeSet<Owner>,
+        fetch_time: SystemTime,
+    ) -> sqlx::Result<()> {
+        let org = org.to_lowercase();
+        let team = team.to_lowercase();
+        let members = members
+            .iter()
+            .map(|member| member.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let fetch_time_unix = fetch_time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("system time is always after unix epoch")
+            .as_secs() as i64;
+
+        sqlx::query!(
+            "INSERT OR REPLACE INTO team_members (org, team, members, fetched_unix) VALUES (?, ?, ?, ?)",
+            org,
+            team,
+            members,
+            fetch_time_unix,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The last change in requirements noted on PR `pr_id`'s comment, as the
+    /// requirement before and after the change.
+    pub async fn get_requirement_change(
+        &self,
+        pr_id: u64,
+    ) -> sqlx::Result<Option<(String, String)>> {
+        let pr_id = pr_id as i64;
+        let row = sqlx::query!(
+            "SELECT previous, current FROM requirement_changes WHERE pr_id = ?",
+            pr_id,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| (row.previous, row.current)))
+    }
+
+    pub async fn update_requirement_change(
+        &self,
+        pr_id: u64,
+        previous: &str,
+        current: &str,
+    ) -> sqlx::Result<()> {
+        let pr_id = pr_id as i64;
+        sqlx::query!(
+            "INSERT OR REPLACE INTO requirement_changes (pr_id, previous, current) VALUES (?, ?, ?)",
+            pr_id,
+            previous,
+            current,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// How PR `pr_id`'s CODEOWNERS edits change file ownership, if it was
+    /// worked out for the same `change`: the same CODEOWNERS blobs, and the
+    /// same files at the same head ref.
+    pub async fn get_ownership_diff(
+        &self,
+        pr_id: u64,
+        change: &CodeownersChange,
+    ) -> sqlx::Result<Option<OwnershipDiff>> {
+        let pr_id = pr_id as i64;
+        let (base_sha, head_sha) = change.shas();
+        let (base_sha, head_sha) = (base_sha.unwrap_or_default(), head_sha.unwrap_or_default());
+        let row = sqlx::query!(
+            "SELECT diff FROM ownership_diffs WHERE pr_id = ? AND base_sha = ? AND head_sha = ? AND head_ref = ?",
+            pr_id,
+            base_sha,
+            head_sha,
+            change.head_ref,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| serde_json::from_str(&row.diff).map_err(|e| sqlx::Error::Decode(e.into())))
+            .transpose()
+    }
+
+    pub async fn update_ownership_diff(
+        &self,
+        pr_id: u64,
+        change: &CodeownersChange,
+        diff: &OwnershipDiff,
+    ) -> sqlx::Result<()> {
+        let pr_id = pr_id as i64;
+        let (base_sha, head_sha) = change.shas();
+        let (base_sha, head_sha) = (base_sha.unwrap_or_default(), head_sha.unwrap_or_default());
+        let diff = serde_json::to_string(diff).map_err(|e| sqlx::Error::Encode(e.into()))?;
+
+        sqlx::query!(
+            "INSERT OR REPLACE INTO ownership_diffs (pr_id, base_sha, head_sha, head_ref, diff) VALUES (?, ?, ?, ?, ?)",
+            pr_id,
+            base_sha,
+            head_sha,
+            change.head_ref,
+            diff,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Where CODEOWNERS was last found on `git_ref`, and the SHA of its blob.
+    pub async fn get_codeowners_ref(
+        &self,
+        git_ref: &str,
+    ) -> sqlx::Result<Option<(CodeownersLocation, String)>> {
+        let row = sqlx::query!(
+            "SELECT path, etag, commit_etag, sha FROM codeowners_refs WHERE git_ref = ?",
+            git_ref,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| {
+            (
+                CodeownersLocation {
+                    path: row.path,
+                    etag: row.etag,
+                    commit_etag: row.commit_etag,
+                },
+                row.sha,
+            )
+        }))
+    }
+
+    /// Records that `git_ref` now uses the CODEOWNERS blob `sha`, storing its
+    /// `content`. Blobs no branch uses anymore are deleted.
+    pub async fn update_codeowners_ref(
+        &self,
+        git_ref: &str,
+        location: &CodeownersLocation,
+        sha: &str,
+        content: &str,
+    ) -> sqlx::Result<()> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query!(
+            "INSERT OR REPLACE INTO codeowners_blobs (sha, content) VALUES (?, ?)",
+            sha,
+            content,
+        )
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query!(
+            "INSERT OR REPLACE INTO codeowners_refs (git_ref, path, etag, commit_etag, sha) VALUES (?, ?, ?, ?, ?)",
+            git_ref,
+            location.path,
+            location.etag,
+            location.commit_etag,
+            sha,
+        )
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM codeowners_blobs WHERE sha NOT IN (SELECT sha FROM codeowners_refs)"
+        )
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    /// Records where `git_ref`'s CODEOWNERS file is now, when the file itself
+    /// didn't change.
+    pub async fn update_codeowners_location(
+        &self,
+        git_ref: &str,
+        location: &CodeownersLocation,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            "UPDATE codeowners_refs SET path = ?, etag = ?, commit_etag = ? WHERE git_ref = ?",
+            location.path,
+            location.etag,
+            location.commit_etag,
+            git_ref,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Content of the CODEOWNERS blob with `sha`, if a branch uses it.
+    pub async fn get_codeowners_content(&self, sha: &str) -> sqlx::Result<Option<String>> {
+        let row = sqlx::query!("SELECT content FROM codeowners_blobs WHERE sha = ?", sha)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| row.content))
+    }
+
+    pub fn get_parsed_codeowners(&self, sha: &str) -> Option<Arc<CodeownersRules>> {
+        self.parsed_codeowners
+            .lock()
+            .expect("parsed codeowners lock poisoned")
+            .get(sha)
+            .cloned()
+    }
+
+    pub fn insert_parsed_codeowners(
+        &self,
+        sha: &str,
+        codeowners: CodeownersRules,
+    ) -> Arc<CodeownersRules> {
+        let codeowners = Arc::new(codeowners);
+        self.parsed_codeowners
+            .lock()
+            .expect("parsed codeowners lock poisoned")
+            .insert(sha.to_string(), codeowners.clone());
+        codeowners
+    }
+
+    /// Forgets the parsed CODEOWNERS files other than those in `in_use`.
+    pub fn retain_parsed_codeowners(&self, in_use: &HashSet<String>) {
+        self.parsed_codeowners
+            .lock()
+            .expect("parsed codeowners lock poisoned")
+            .retain(|sha, _| in_use.contains(sha));
+    }
 }
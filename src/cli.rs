@@ -1,81 +1,560 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use jsonwebtoken::EncodingKey;
 use min_review_bot::{
-    conditional::OwnersConditional,
+    conditional::{Approvals, OwnersConditional, SolveMode},
+    config::{UnownedFiles, WeightsConfig},
+    coverage::CoverageReport,
     github::{GithubSource, Repo, RepoConnector},
+    lint,
+    owner::{Owner, TeamMembers},
+    provenance::TrackedConditional,
+    render::RenderFormat,
+    report::OwnershipReport,
+    rules::{ApprovalCount, CodeownersRules},
+    who_owns::who_owns,
 };
 use octocrab::{Octocrab, models::AppId};
-use std::{env, path::PathBuf};
+use std::{
+    collections::BTreeSet,
+    env,
+    path::{Path, PathBuf},
+    process,
+};
 
 #[derive(Parser, Debug)] // requires `derive` feature
 #[command(term_width = 0)] // Just to make testing across clap features easier
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    #[arg(long, short)]
-    pr_num: u64,
-    #[arg(long, short)]
-    repo: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(long, short, required = true)]
+    pr_num: Option<u64>,
+    #[arg(long, short, required = true)]
+    repo: Option<String>,
     #[arg(long, short)]
     exclude_owners: Vec<String>,
     #[arg(long, short)]
     update_github: bool,
+    /// How many alternative sets of reviewers to list
+    #[arg(long, default_value_t = 3)]
+    alternatives: usize,
+    /// TOML file with the cost of each reviewer, in the same format as the
+    /// `[weights]` table of the daemon's config. Also suggests the cheapest
+    /// set of reviewers
+    #[arg(long)]
+    weights: Option<PathBuf>,
+    /// Use this requirement instead of the one computed from CODEOWNERS, to
+    /// check what-if scenarios. Uses the same syntax as the PR comment, e.g.
+    /// `(@alice && (@bob || @org/team))`
+    #[arg(long)]
+    conditional: Option<OwnersConditional>,
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Expand teams into their members before looking for the smallest set of
+    /// reviewers, so one person on several teams can cover all of them
+    #[arg(long)]
+    expand_teams: bool,
+    /// Require this many approvals for files matching a CODEOWNERS pattern,
+    /// e.g. `/security/=2`. Can be given more than once, the last match wins
+    #[arg(long)]
+    approval_count: Vec<ApprovalCount>,
+    /// Require a review from any of these owners for files with no owners in
+    /// CODEOWNERS
+    #[arg(long, conflicts_with = "block_unowned")]
+    fallback_owner: Vec<String>,
+    /// Report the PR as blocked until files with no owners in CODEOWNERS get
+    /// some
+    #[arg(long)]
+    block_unowned: bool,
+    /// Also show the requirement as a `checklist`, a `mermaid` flowchart or a
+    /// `dot` graph
+    #[arg(long, default_value = "expression")]
+    render: RenderFormat,
+    /// Path of the CODEOWNERS file in the repo. By default we look wherever
+    /// GitHub does: `.github/`, the root of the repo, then `docs/`
+    #[arg(long)]
+    codeowners_path: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check a CODEOWNERS file for mistakes. Exits with an error if there are
+    /// any
+    Lint(LintArgs),
+    /// Report which files in the repo have owners, and who they are
+    Coverage(CoverageArgs),
+    /// Show every CODEOWNERS rule that matches each path, and which ones
+    /// decide its owners
+    WhoOwns(WhoOwnsArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct LintArgs {
+    /// Local CODEOWNERS file to check, instead of reading it from `--repo`
+    #[arg(long, required_unless_present = "repo")]
+    codeowners: Option<PathBuf>,
+    #[arg(long, short)]
+    repo: Option<String>,
+    /// Branch, tag or commit to read CODEOWNERS from. Defaults to the default
+    /// branch
+    #[arg(long = "ref", requires = "repo", conflicts_with = "codeowners")]
+    git_ref: Option<String>,
+    /// Path of the CODEOWNERS file in the repo. By default we look wherever
+    /// GitHub does
+    #[arg(long, requires = "repo", conflicts_with = "codeowners")]
+    codeowners_path: Option<String>,
+    /// Local git checkout to match the rules against, to find rules that don't
+    /// match any file or are always overridden by later ones
+    #[arg(long)]
+    tree: Option<PathBuf>,
+    /// Check that every user and team in the file exists
+    #[arg(long, requires = "repo")]
+    check_owners: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct CoverageArgs {
+    #[arg(long, short)]
+    repo: String,
+    /// Branch, tag or commit to report on. Defaults to the default branch
+    #[arg(long = "ref")]
+    git_ref: Option<String>,
+    /// Path of the CODEOWNERS file in the repo. By default we look wherever
+    /// GitHub does
+    #[arg(long)]
+    codeowners_path: Option<String>,
+    #[arg(long, value_enum, default_value_t = CoverageFormat::Text)]
+    format: CoverageFormat,
+}
+
+#[derive(clap::Args, Debug)]
+struct WhoOwnsArgs {
+    /// Paths relative to the root of the repo
+    #[arg(required = true)]
+    paths: Vec<String>,
+    /// Local CODEOWNERS file to use, instead of reading it from `--repo`
+    #[arg(long, required_unless_present = "repo")]
+    codeowners: Option<PathBuf>,
+    #[arg(long, short)]
+    repo: Option<String>,
+    /// Branch, tag or commit to read CODEOWNERS from. Defaults to the default
+    /// branch
+    #[arg(long = "ref", requires = "repo", conflicts_with = "codeowners")]
+    git_ref: Option<String>,
+    /// Path of the CODEOWNERS file in the repo. By default we look wherever
+    /// GitHub does
+    #[arg(long, requires = "repo", conflicts_with = "codeowners")]
+    codeowners_path: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum CoverageFormat {
+    /// Human readable summary
+    Text,
+    /// The JSON described in `min_review_bot::coverage`
+    Json,
+    /// One row per unowned file, owner and owner set of each mixed directory
+    Csv,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// Human readable summary, followed by the PR comment
+    Text,
+    /// The versioned JSON report described in `min_review_bot::report`
+    Json,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    dotenv::dotenv()?;
     let args = Args::parse();
-    let exclude_owners = args.exclude_owners.into_iter().collect();
-
-    let pem_path = env::var("GITHUB_PRIVATE_KEY_PATH")?;
-    let pem_data = tokio::fs::read(PathBuf::from(pem_path)).await?;
-    let repo = Repo::from_path(&args.repo)?;
+    match args.command {
+        Some(Command::Lint(lint_args)) => return run_lint(lint_args).await,
+        Some(Command::Coverage(coverage_args)) => return run_coverage(coverage_args).await,
+        Some(Command::WhoOwns(who_owns_args)) => return run_who_owns(who_owns_args).await,
+        None => {}
+    }
+    let pr_num = args.pr_num.expect("required by clap");
+    let repo_path = args.repo.expect("required by clap");
+    let exclude_owners = args
+        .exclude_owners
+        .iter()
+        .map(|owner| Owner::from(owner.as_str()))
+        .collect();
 
-    octocrab::initialise(
-        Octocrab::builder()
-            .app(
-                AppId(env::var("GITHUB_APP_ID")?.parse()?),
-                EncodingKey::from_rsa_pem(&pem_data)?,
-            )
-            .build()?,
-    );
+    let weights: Option<WeightsConfig> = match &args.weights {
+        Some(path) => Some(toml::de::from_slice(&tokio::fs::read(path).await?)?),
+        None => None,
+    };
 
-    let repo_connector = RepoConnector::new(GithubSource::new_authorized(repo.user()).await?, repo);
-    let changed_files = repo_connector.get_pr_changed_files(args.pr_num).await?;
-    let codeowners_data = repo_connector.get_codeowners_content().await?;
-    let codeowners = codeowners::from_reader(codeowners_data.as_bytes());
+    let repo_connector = connect(&repo_path).await?;
+    let changed_files = repo_connector.get_pr_changed_files(pr_num).await?;
+    let pr = repo_connector.get_pr(pr_num).await?;
+    // GitHub uses the CODEOWNERS file on the branch the PR targets
+    let base_ref = &pr.base.ref_field;
+    let codeowners_file = repo_connector
+        .get_codeowners_file(args.codeowners_path.as_deref(), Some(base_ref))
+        .await?;
+    let codeowners =
+        CodeownersRules::parse(&codeowners_file.content).with_approval_counts(args.approval_count);
 
     let changed_files_slc: Vec<&str> = changed_files.iter().map(|f| f.as_ref()).collect();
-    let conditional = OwnersConditional::from_codeowners(&codeowners, &changed_files_slc[..])
-        .remove_all(&exclude_owners)
-        .unwrap_or(OwnersConditional::And(Vec::new()))
-        .reduce();
+    // GitHub doesn't let authors approve their own PRs
+    let author = pr.user.as_ref().map(|user| Owner::User(user.login.clone()));
+    let unowned_files = if args.block_unowned {
+        UnownedFiles::Block
+    } else if !args.fallback_owner.is_empty() {
+        UnownedFiles::Fallback(
+            args.fallback_owner
+                .iter()
+                .map(|owner| Owner::from(owner.as_str()))
+                .collect(),
+        )
+    } else {
+        UnownedFiles::Allow
+    };
+    let mut tracked = TrackedConditional::from_codeowners(&codeowners, &changed_files_slc[..]);
+    if let UnownedFiles::Fallback(owners) = &unowned_files {
+        tracked = tracked.with_fallback_owners(owners);
+    }
+    let mut tracked = tracked.remove_all(&exclude_owners);
+    if let Some(author) = &author {
+        tracked = tracked.remove_author(author);
+    }
+    let tracked = tracked.reduce();
+    // What-if requirements don't come from CODEOWNERS, so there's no
+    // provenance to show for them
+    let (conditional, tracked) = match args.conditional {
+        Some(conditional) => (conditional.reduce(), TrackedConditional::default()),
+        None => (tracked.conditional(), tracked),
+    };
+
+    let team_members = if args.expand_teams {
+        let mut team_members = TeamMembers::default();
+        for owner in conditional.teams() {
+            if let Owner::Team { org, team } = &owner {
+                let members = repo_connector.get_team_members(org, team).await?;
+                team_members.insert(owner.clone(), members);
+            }
+        }
+        Some(team_members)
+    } else {
+        None
+    };
+    let solved = match &team_members {
+        Some(team_members) => conditional.clone().expand_teams(team_members).reduce(),
+        None => conditional.clone(),
+    };
+
+    let minimum = solved.minimum_owner_sets(SolveMode::Auto);
+    let blocked_files = min_review_bot::blocked_files(&tracked, &unowned_files);
+    let report = OwnershipReport::new(
+        &codeowners,
+        &changed_files_slc[..],
+        conditional.clone(),
+        minimum.clone(),
+        blocked_files.clone(),
+    );
 
     let file_owners = min_review_bot::display_file_owners(&codeowners, &changed_files_slc[..]);
-    println!("Required reviewers: {conditional}");
+    let minimum_owners = min_review_bot::display_minimum_owner_sets(&minimum);
+    let team_coverage = match &team_members {
+        Some(team_members) => {
+            min_review_bot::display_team_coverage(&conditional, &minimum, team_members)
+        }
+        None => String::new(),
+    };
+    let cheapest = weights.as_ref().map(|weights| {
+        solved.cheapest_owner_set(SolveMode::Auto, |owner| weights.weight_of(owner))
+    });
+    let cheapest_owners = match &cheapest {
+        Some(cheapest) => format!(
+            "\nCheapest group of reviewers: {}\n",
+            min_review_bot::display_weighted_owner_set(cheapest.as_ref())
+        ),
+        None => String::new(),
+    };
+    let alternatives = match &weights {
+        Some(weights) => {
+            solved.alternative_owner_sets(args.alternatives, |owner| weights.weight_of(owner))
+        }
+        None => solved.alternative_owner_sets(args.alternatives, |_| 0),
+    };
+    let alternative_lines = alternatives
+        .iter()
+        .enumerate()
+        .map(|(idx, alternative)| {
+            let owners = alternative
+                .owners
+                .keys()
+                .map(|owner| owner.to_string())
+                .collect::<Vec<_>>();
+            match &weights {
+                Some(_) => format!(
+                    "Alternative {}: {} (cost {})",
+                    idx + 1,
+                    owners.join(", "),
+                    alternative.cost
+                ),
+                None => format!("Alternative {}: {}", idx + 1, owners.join(", ")),
+            }
+        })
+        .collect::<Vec<_>>();
+    let alternatives =
+        min_review_bot::display_alternative_owner_sets(&alternatives, weights.is_some());
+    let mut approvals = Approvals::from_logins(repo_connector.get_pr_approvers(pr_num).await?);
+    if let Some(team_members) = &team_members {
+        approvals = approvals.with_team_members(team_members);
+    }
+    let approval_status =
+        min_review_bot::display_approval_status(&conditional, &approvals, &blocked_files);
+    let rendered_requirement =
+        min_review_bot::display_rendered_requirement(&conditional, args.render, &approvals);
+    let provenance = min_review_bot::display_provenance(&tracked);
+    let optional_reviews = min_review_bot::display_optional_reviews(&tracked, &approvals);
+    let ownership_diff = match repo_connector
+        .get_codeowners_change(
+            args.codeowners_path.as_deref(),
+            &changed_files,
+            base_ref,
+            &pr.head.sha,
+        )
+        .await?
+    {
+        Some(change) => Some(repo_connector.get_ownership_diff(&change).await?),
+        None => None,
+    };
+    let codeowners_changes = min_review_bot::display_codeowners_changes(ownership_diff.as_ref());
+    let unowned_section = min_review_bot::display_unowned_files(&tracked, &unowned_files);
+    let no_eligible_owner = match &author {
+        Some(author) => min_review_bot::display_no_eligible_owner(&tracked, author),
+        None => String::new(),
+    };
+    let report_block = report.to_comment_block()?;
 
     let comment = format!(
         r#"# File Owners
-The minimum set of reviewers required are:
+{no_eligible_owner}The minimum set of reviewers required are:
 `{conditional}`
+{rendered_requirement}
+Smallest group of reviewers that satisfies this: {minimum_owners}
+{team_coverage}{cheapest_owners}{alternatives}
+## Approval status
+{approval_status}
+
+{codeowners_changes}{optional_reviews}{unowned_section}<details>
+    <summary>Why each reviewer is needed</summary>
+
+{provenance}
+</details>
 <details>
     <summary>Details</summary>
     {file_owners}
-</details>"#
+</details>
+{report_block}"#
     );
 
+    match args.format {
+        Format::Text => {
+            println!("Required reviewers: {conditional}");
+            for file in &tracked.unowned_files {
+                println!("Unowned file: {file}");
+            }
+            for clause in &tracked.no_eligible_owner {
+                println!("No eligible owner: {}", clause.conditional);
+            }
+            println!("Minimum reviewer set: {minimum}");
+            if let Some(cheapest) = &cheapest {
+                println!(
+                    "Cheapest reviewer set: {}",
+                    min_review_bot::display_weighted_owner_set(cheapest.as_ref())
+                );
+            }
+            for line in alternative_lines {
+                println!("{line}");
+            }
+            if args.update_github {
+                println!("Updating comment on PR {}/{}:", repo_path, pr_num);
+            } else {
+                println!(
+                    "Would have updated coment on PR {}/{} to:",
+                    repo_path, pr_num
+                );
+            }
+            println!("{comment}");
+        }
+        Format::Json => println!("{}", report.to_json()?),
+    }
+
     if args.update_github {
-        println!("Updating comment on PR {}/{}:", args.repo, args.pr_num);
-        println!("{comment}");
         repo_connector
-            .add_or_edit_comment(args.pr_num, comment, env::var("BOT_USERNAME")?)
+            .add_or_edit_comment(pr_num, comment, env::var("BOT_USERNAME")?)
             .await?;
-    } else {
-        println!(
-            "Would have updated coment on PR {}/{} to:",
-            args.repo, args.pr_num
-        );
-        println!("{comment}");
     }
 
     Ok(())
 }
+
+/// Authenticates as the GitHub app installed on `repo_path`.
+async fn connect(repo_path: &str) -> anyhow::Result<RepoConnector<GithubSource>> {
+    dotenv::dotenv()?;
+    let pem_path = env::var("GITHUB_PRIVATE_KEY_PATH")?;
+    let pem_data = tokio::fs::read(PathBuf::from(pem_path)).await?;
+    let repo = Repo::from_path(repo_path)?;
+
+    octocrab::initialise(
+        Octocrab::builder()
+            .app(
+                AppId(env::var("GITHUB_APP_ID")?.parse()?),
+                EncodingKey::from_rsa_pem(&pem_data)?,
+            )
+            .build()?,
+    );
+
+    Ok(RepoConnector::new(
+        GithubSource::new_authorized(repo.user()).await?,
+        repo,
+    ))
+}
+
+async fn run_lint(args: LintArgs) -> anyhow::Result<()> {
+    // A local file only needs GitHub to look up owners
+    let repo_connector = match &args.repo {
+        Some(repo_path) if args.codeowners.is_none() || args.check_owners => {
+            Some(connect(repo_path).await?)
+        }
+        _ => None,
+    };
+    let (name, content) = read_codeowners(
+        args.codeowners.as_deref(),
+        repo_connector.as_ref(),
+        args.codeowners_path.as_deref(),
+        args.git_ref.as_deref(),
+    )
+    .await?;
+
+    let files = match &args.tree {
+        Some(tree) => Some(list_tree(tree)?),
+        None => None,
+    };
+    let file_refs = files
+        .as_ref()
+        .map(|files| files.iter().map(String::as_str).collect::<Vec<_>>());
+    let codeowners = CodeownersRules::parse(&content);
+    let mut findings = lint::lint(&codeowners, file_refs.as_deref());
+
+    if args.check_owners
+        && let Some(repo_connector) = &repo_connector
+    {
+        let owners: BTreeSet<Owner> = codeowners
+            .rules()
+            .iter()
+            .flat_map(|rule| rule.owners.iter().cloned())
+            .collect();
+        let missing = repo_connector.get_missing_owners(&owners).await?;
+        findings.extend(lint::unknown_owners(&codeowners, &missing));
+        findings.sort();
+    }
+
+    for finding in &findings {
+        println!("{name}:{}: {}", finding.line, finding.problem);
+    }
+    if !findings.is_empty() {
+        eprintln!("Found {} problems in {name}", findings.len());
+        process::exit(1);
+    }
+    Ok(())
+}
+
+async fn run_coverage(args: CoverageArgs) -> anyhow::Result<()> {
+    let repo_connector = connect(&args.repo).await?;
+    let codeowners_file = repo_connector
+        .get_codeowners_file(args.codeowners_path.as_deref(), args.git_ref.as_deref())
+        .await?;
+    let codeowners = CodeownersRules::parse(&codeowners_file.content);
+    let files = repo_connector
+        .get_tree_files(args.git_ref.as_deref())
+        .await?;
+    let files: Vec<&str> = files.iter().map(String::as_str).collect();
+
+    let report = CoverageReport::new(&codeowners, &files);
+    match args.format {
+        CoverageFormat::Text => print!("{}", report.to_text()),
+        CoverageFormat::Json => println!("{}", report.to_json()?),
+        CoverageFormat::Csv => print!("{}", report.to_csv()),
+    }
+    Ok(())
+}
+
+async fn run_who_owns(args: WhoOwnsArgs) -> anyhow::Result<()> {
+    let repo_connector = match &args.repo {
+        Some(repo_path) if args.codeowners.is_none() => Some(connect(repo_path).await?),
+        _ => None,
+    };
+    let (_, content) = read_codeowners(
+        args.codeowners.as_deref(),
+        repo_connector.as_ref(),
+        args.codeowners_path.as_deref(),
+        args.git_ref.as_deref(),
+    )
+    .await?;
+    let codeowners = CodeownersRules::parse(&content);
+    for diagnostic in codeowners.diagnostics() {
+        eprintln!("Skipping CODEOWNERS line: {diagnostic}");
+    }
+
+    let explanations = args
+        .paths
+        .iter()
+        .map(|path| who_owns(&codeowners, path).to_string())
+        .collect::<Vec<_>>();
+    print!("{}", explanations.join("\n"));
+    Ok(())
+}
+
+/// Reads the local CODEOWNERS file at `local`, or else the one in the repo.
+/// Returns a name for the file to show in the output, and its content.
+async fn read_codeowners(
+    local: Option<&Path>,
+    repo_connector: Option<&RepoConnector<GithubSource>>,
+    codeowners_path: Option<&str>,
+    git_ref: Option<&str>,
+) -> anyhow::Result<(String, String)> {
+    match (local, repo_connector) {
+        (Some(path), _) => Ok((
+            path.display().to_string(),
+            tokio::fs::read_to_string(path).await?,
+        )),
+        (None, Some(repo_connector)) => {
+            let file = repo_connector
+                .get_codeowners_file(codeowners_path, git_ref)
+                .await?;
+            // Only used to name the file in the output
+            let name = codeowners_path.unwrap_or("CODEOWNERS").to_string();
+            Ok((name, file.content))
+        }
+        (None, None) => unreachable!("clap requires --codeowners or --repo"),
+    }
+}
+
+/// Lists the files tracked in the git checkout at `tree`, relative to its
+/// root.
+fn list_tree(tree: &Path) -> anyhow::Result<Vec<String>> {
+    let output = process::Command::new("git")
+        .arg("-C")
+        .arg(tree)
+        .args(["ls-files", "-z"])
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "could not list files in {}: {}",
+            tree.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?
+        .split('\0')
+        .filter(|file| !file.is_empty())
+        .map(str::to_string)
+        .collect())
+}
@@ -0,0 +1,729 @@
+//! Parser for CODEOWNERS files, following the syntax and matching rules GitHub
+//! documents at
+//! <https://docs.github.com/en/repositories/managing-your-repositorys-settings-and-features/customizing-your-repository/about-code-owners>.
+//!
+//! Patterns work like in `.gitignore`, except that `!` negation and `[ ]`
+//! character ranges aren't supported, and paths are case sensitive. GitHub
+//! skips lines with invalid syntax entirely, so we do too, but also report them
+//! as diagnostics.
+//!
+//! We also support GitLab's sections, which GitHub doesn't have. A line like
+//! `[Name] @owner` starts a section, `^[Name]` an optional one and `[Name][2]`
+//! one that needs two approvals. Each section picks its own last matching rule
+//! for a file, and rules without owners get the section's default owners.
+use crate::owner::Owner;
+
+/// Where something is in a CODEOWNERS file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Span {
+    /// 1-indexed line.
+    pub line: usize,
+    /// Byte offset into the line where the span starts.
+    pub start: usize,
+    /// Byte offset into the line right after the span ends.
+    pub end: usize,
+}
+
+/// A valid line of a CODEOWNERS file.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    /// Covers the pattern and owners, but not any trailing comment.
+    pub span: Span,
+    pub pattern: Pattern,
+    /// Empty if the rule removes ownership of the files it matches.
+    pub owners: Vec<Owner>,
+    /// Index into `CodeownersFile::sections`, or `None` for rules before the
+    /// first section.
+    pub section: Option<usize>,
+}
+
+/// A GitLab style section of a CODEOWNERS file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    /// Where the section is first declared. Sections with the same name are
+    /// merged, taking the settings of the first one.
+    pub span: Span,
+    /// Reviews from optional sections are advisory, rather than required.
+    pub optional: bool,
+    /// How many different owners need to approve files in this section.
+    pub approvals: usize,
+    /// Owners for the rules in the section that don't list any.
+    pub default_owners: Vec<Owner>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{kind} on line {}", .span.line)]
+pub struct Diagnostic {
+    /// The part of the line that's wrong.
+    pub span: Span,
+    pub kind: DiagnosticKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, thiserror::Error)]
+pub enum DiagnosticKind {
+    #[error("`!` negation is not supported")]
+    Negation,
+    #[error("`[ ]` character ranges are not supported")]
+    CharacterRange,
+    #[error("pattern ends with an unfinished `\\` escape")]
+    TrailingEscape,
+    #[error("`{0}` is not a valid owner, expected `@user`, `@org/team` or an email address")]
+    InvalidOwner(String),
+    #[error("invalid section header, expected `[Name]`, `^[Name]` or `[Name][2]`")]
+    InvalidSection,
+}
+
+/// The rules of a CODEOWNERS file, in the order they appear, along with the
+/// lines GitHub would skip.
+#[derive(Debug, Clone, Default)]
+pub struct CodeownersFile {
+    pub rules: Vec<Rule>,
+    /// Empty unless the file uses sections.
+    pub sections: Vec<Section>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+pub fn parse(content: &str) -> CodeownersFile {
+    let mut file = CodeownersFile::default();
+    let mut section = None;
+    for (idx, line) in content.lines().enumerate() {
+        let line_num = idx + 1;
+        let span = |(start, end): (usize, usize)| Span {
+            line: line_num,
+            start,
+            end,
+        };
+
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('[') || trimmed.starts_with("^[") {
+            let start = line.len() - trimmed.len();
+            match parse_section_header(line, start) {
+                Ok(header) => {
+                    let tokens = tokenize(line, header.owners_start);
+                    let end = tokens.last().map_or(header.owners_start, |token| token.1);
+                    let default_owners = parse_owners(line, &tokens, &span, &mut file.diagnostics);
+                    section = Some(file.add_section(Section {
+                        name: header.name.to_string(),
+                        span: span((start, end)),
+                        optional: header.optional,
+                        approvals: header.approvals,
+                        default_owners,
+                    }));
+                }
+                Err(kind) => file.diagnostics.push(Diagnostic {
+                    span: span((start, line.trim_end().len())),
+                    kind,
+                }),
+            }
+            continue;
+        }
+
+        let tokens = tokenize(line, 0);
+        let Some((pattern_token, owner_tokens)) = tokens.split_first() else {
+            continue;
+        };
+        let mut diagnostics = vec![];
+        let pattern = Pattern::new(&line[pattern_token.0..pattern_token.1]).map_err(|kind| {
+            diagnostics.push(Diagnostic {
+                span: span(*pattern_token),
+                kind,
+            })
+        });
+        let mut owners = parse_owners(line, owner_tokens, &span, &mut diagnostics);
+        if owners.is_empty()
+            && let Some(section) = section
+        {
+            owners = file.sections[section].default_owners.clone();
+        }
+
+        match pattern {
+            Ok(pattern) if diagnostics.is_empty() => file.rules.push(Rule {
+                span: span((pattern_token.0, tokens.last().expect("not empty").1)),
+                pattern,
+                owners,
+                section,
+            }),
+            _ => file.diagnostics.append(&mut diagnostics),
+        }
+    }
+    file
+}
+
+impl CodeownersFile {
+    /// Adds a section, or finds the one with the same name, ignoring case.
+    /// Returns its index.
+    fn add_section(&mut self, section: Section) -> usize {
+        match self
+            .sections
+            .iter()
+            .position(|existing| existing.name.eq_ignore_ascii_case(&section.name))
+        {
+            Some(idx) => idx,
+            None => {
+                self.sections.push(section);
+                self.sections.len() - 1
+            }
+        }
+    }
+}
+
+/// The `^[Name][2]` part of a section header.
+struct SectionHeader<'a> {
+    name: &'a str,
+    optional: bool,
+    approvals: usize,
+    /// Byte offset of the line where the default owners start.
+    owners_start: usize,
+}
+
+/// Parses the section header starting at byte `start` of `line`.
+fn parse_section_header(line: &str, start: usize) -> Result<SectionHeader<'_>, DiagnosticKind> {
+    let header = &line[start..];
+    let optional = header.starts_with('^');
+    let header = header.strip_prefix('^').unwrap_or(header);
+    let (name, mut rest) = header
+        .strip_prefix('[')
+        .and_then(|header| header.split_once(']'))
+        .ok_or(DiagnosticKind::InvalidSection)?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(DiagnosticKind::InvalidSection);
+    }
+    let mut approvals = 1;
+    if let Some(count) = rest.strip_prefix('[') {
+        let (count, after) = count
+            .split_once(']')
+            .ok_or(DiagnosticKind::InvalidSection)?;
+        approvals = count
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&approvals| approvals > 0)
+            .ok_or(DiagnosticKind::InvalidSection)?;
+        rest = after;
+    }
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return Err(DiagnosticKind::InvalidSection);
+    }
+
+    Ok(SectionHeader {
+        name,
+        optional,
+        approvals,
+        owners_start: line.len() - rest.len(),
+    })
+}
+
+/// Parses the owners at each of `tokens`, adding a diagnostic for every
+/// invalid one.
+fn parse_owners(
+    line: &str,
+    tokens: &[(usize, usize)],
+    span: &impl Fn((usize, usize)) -> Span,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<Owner> {
+    let mut owners = vec![];
+    for &(start, end) in tokens {
+        match parse_owner(&line[start..end]) {
+            Some(owner) => owners.push(owner),
+            None => diagnostics.push(Diagnostic {
+                span: span((start, end)),
+                kind: DiagnosticKind::InvalidOwner(line[start..end].to_string()),
+            }),
+        }
+    }
+    owners
+}
+
+/// Byte ranges of the whitespace separated tokens on a line, starting from
+/// byte `from` and up to a comment. Whitespace escaped with `\` doesn't end a
+/// token.
+fn tokenize(line: &str, from: usize) -> Vec<(usize, usize)> {
+    let mut tokens = vec![];
+    let mut chars = line[from..]
+        .char_indices()
+        .map(|(idx, c)| (from + idx, c))
+        .peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c == '#' {
+            break;
+        }
+        let mut end = line.len();
+        while let Some((idx, c)) = chars.next() {
+            if c == '\\' {
+                chars.next();
+            } else if c.is_whitespace() {
+                end = idx;
+                break;
+            }
+        }
+        tokens.push((start, end));
+    }
+    tokens
+}
+
+/// Owners are written as `@user`, `@org/team` or an email address.
+fn parse_owner(token: &str) -> Option<Owner> {
+    let valid = match token.strip_prefix('@') {
+        Some(handle) => {
+            let name_valid = match handle.split_once('/') {
+                Some((org, team)) => !org.is_empty() && !team.is_empty() && !team.contains('/'),
+                None => !handle.is_empty(),
+            };
+            name_valid && !handle.contains('@')
+        }
+        None => token.split_once('@').is_some_and(|(name, domain)| {
+            !name.is_empty() && !domain.is_empty() && !domain.contains('@')
+        }),
+    };
+    valid.then(|| Owner::from(token))
+}
+
+/// A compiled CODEOWNERS pattern.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    source: String,
+    segments: Vec<Segment>,
+    /// Set by a trailing `/`, so the pattern only matches directories.
+    directory_only: bool,
+    /// Set by a trailing `/*`, which GitHub only matches against the files
+    /// directly in a directory, not those further down.
+    children_only: bool,
+    // Part of the pattern every matching path contains, to rule out most
+    // paths without running the whole match
+    literal: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    /// `**`, which matches any number of directories, including none.
+    AnyDirectories,
+    /// Matches a single file or directory name.
+    Name(Vec<Token>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    /// `?`
+    AnyChar,
+    /// `*`
+    AnyChars,
+}
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Pattern, DiagnosticKind> {
+        if source.starts_with('!') {
+            return Err(DiagnosticKind::Negation);
+        }
+
+        // Split on unescaped slashes, keeping track of whether there's one at
+        // the start, in the middle or at the end
+        let mut names: Vec<Vec<Token>> = vec![vec![]];
+        let mut chars = source.chars();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '\\' => Token::Literal(chars.next().ok_or(DiagnosticKind::TrailingEscape)?),
+                '[' | ']' => return Err(DiagnosticKind::CharacterRange),
+                '?' => Token::AnyChar,
+                '*' => Token::AnyChars,
+                '/' => {
+                    names.push(vec![]);
+                    continue;
+                }
+                c => Token::Literal(c),
+            };
+            names.last_mut().expect("never empty").push(token);
+        }
+
+        let directory_only = names.len() > 1 && names.last().is_some_and(Vec::is_empty);
+        if directory_only {
+            names.pop();
+        }
+        let leading_slash = source.starts_with('/');
+        if leading_slash {
+            names.remove(0);
+        }
+        // A slash anywhere but the end ties the pattern to the root of the
+        // repo, otherwise it can match at any depth
+        let anchored = leading_slash || names.len() > 1;
+        let mut segments: Vec<Segment> = names
+            .into_iter()
+            .filter(|name| !name.is_empty())
+            .map(|mut name| {
+                if name == [Token::AnyChars, Token::AnyChars] {
+                    Segment::AnyDirectories
+                } else {
+                    // Otherwise a run of stars means the same as one
+                    name.dedup_by(|a, b| *a == Token::AnyChars && *b == Token::AnyChars);
+                    Segment::Name(name)
+                }
+            })
+            .collect();
+        if !anchored {
+            segments.insert(0, Segment::AnyDirectories);
+        }
+        let children_only =
+            !directory_only && segments.last() == Some(&Segment::Name(vec![Token::AnyChars]));
+
+        Ok(Pattern {
+            source: source.to_string(),
+            literal: required_literal(&segments),
+            segments,
+            directory_only,
+            children_only,
+        })
+    }
+
+    /// The pattern as written in the CODEOWNERS file.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the pattern matches `path`, a file relative to the root of the
+    /// repo, either directly or through one of the directories it's in.
+    pub fn matches(&self, path: &str) -> bool {
+        if let Some(literal) = &self.literal
+            && !path.contains(literal.as_str())
+        {
+            return false;
+        }
+        let names: Vec<&str> = path.split('/').filter(|name| !name.is_empty()).collect();
+        self.matches_from(&self.segments, &names, 0)
+    }
+
+    fn matches_from(&self, segments: &[Segment], names: &[&str], matched: usize) -> bool {
+        match segments.split_first() {
+            // Everything left is inside the directory matched so far
+            None if names.is_empty() => !self.directory_only && matched > 0,
+            None => matched > 0 && !self.children_only,
+            // A trailing `**` matches everything inside a directory, but not
+            // the directory itself
+            Some((Segment::AnyDirectories, [])) => !names.is_empty(),
+            Some((Segment::AnyDirectories, rest)) => (0..=names.len())
+                .any(|skip| self.matches_from(rest, &names[skip..], matched + skip)),
+            Some((Segment::Name(tokens), rest)) => {
+                names.split_first().is_some_and(|(name, remaining)| {
+                    name_matches(tokens, name) && self.matches_from(rest, remaining, matched + 1)
+                })
+            }
+        }
+    }
+}
+
+/// Matches a single file or directory name, so wildcards never cross a `/`.
+fn name_matches(tokens: &[Token], name: &str) -> bool {
+    let name: Vec<char> = name.chars().collect();
+    let (mut token_idx, mut name_idx) = (0, 0);
+    // Where to resume from if what follows the last `*` stops matching
+    let mut backtrack = None;
+    while name_idx < name.len() {
+        match tokens.get(token_idx) {
+            Some(Token::AnyChars) => {
+                backtrack = Some((token_idx, name_idx));
+                token_idx += 1;
+                continue;
+            }
+            Some(Token::AnyChar) => {
+                token_idx += 1;
+                name_idx += 1;
+                continue;
+            }
+            Some(Token::Literal(c)) if *c == name[name_idx] => {
+                token_idx += 1;
+                name_idx += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match backtrack {
+            Some((star_idx, star_name_idx)) => {
+                token_idx = star_idx + 1;
+                name_idx = star_name_idx + 1;
+                backtrack = Some((star_idx, star_name_idx + 1));
+            }
+            None => return false,
+        }
+    }
+    tokens[token_idx..]
+        .iter()
+        .all(|token| *token == Token::AnyChars)
+}
+
+/// The longest literal part of the pattern, which can span several names.
+/// Slashes at either end are dropped, since `**` can match without one.
+fn required_literal(segments: &[Segment]) -> Option<String> {
+    let mut literals = vec![String::new()];
+    for (idx, segment) in segments.iter().enumerate() {
+        if idx > 0 {
+            literals.last_mut().expect("never empty").push('/');
+        }
+        match segment {
+            Segment::AnyDirectories => literals.push(String::new()),
+            Segment::Name(tokens) => {
+                for token in tokens {
+                    match token {
+                        Token::Literal(c) => literals.last_mut().expect("never empty").push(*c),
+                        Token::AnyChar | Token::AnyChars => literals.push(String::new()),
+                    }
+                }
+            }
+        }
+    }
+    literals
+        .into_iter()
+        .map(|literal| literal.trim_matches('/').to_string())
+        .max_by_key(|literal| literal.len())
+        .filter(|literal| !literal.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Diagnostic, DiagnosticKind, Pattern, Span, parse};
+    use crate::owner::Owner;
+
+    /// How GitHub matches each pattern, mostly taken from the examples in its
+    /// documentation.
+    const CONFORMANCE: &[(&str, &[&str], &[&str])] = &[
+        // (pattern, matching paths, non-matching paths)
+        ("*", &["a", "a/b/c.rs", ".github/x"], &[]),
+        ("*.js", &["a.js", "src/a/b.js"], &["a.jsx", "a.JS", "js"]),
+        (
+            "/build/logs/",
+            &["build/logs/a", "build/logs/deep/b"],
+            &["build/logs", "x/build/logs/a", "build/logsx/a"],
+        ),
+        (
+            "docs/*",
+            &["docs/getting-started.md"],
+            &["docs/build-app/troubleshooting.md", "x/docs/a.md", "docs"],
+        ),
+        (
+            "apps/",
+            &["apps/a", "deep/apps/b/c"],
+            &["apps", "xapps/a", "deep/apps"],
+        ),
+        ("/docs/", &["docs/a", "docs/b/c"], &["x/docs/a"]),
+        (
+            "src/billing/",
+            &["src/billing/a", "src/billing/b/c"],
+            &["lib/src/billing/a"],
+        ),
+        (
+            "**/logs",
+            &["logs", "build/logs/a", "deeply/nested/logs/b"],
+            &["logsx/a", "build/xlogs"],
+        ),
+        (
+            "/apps/github",
+            &["apps/github", "apps/github/x"],
+            &["apps/githubx", "x/apps/github"],
+        ),
+        (
+            "README.md",
+            &["README.md", "a/README.md"],
+            &["readme.md", "README.mdx"],
+        ),
+        ("src/*.rs", &["src/a.rs"], &["src/a/b.rs", "x/src/a.rs"]),
+        ("a/**/b", &["a/b", "a/x/b", "a/x/y/b/c"], &["x/a/b", "a/bx"]),
+        ("/a/**", &["a/b", "a/b/c"], &["a", "b/a/c"]),
+        ("doc?/", &["docs/a"], &["doc/a", "docss/a"]),
+        ("my\\ file.txt", &["my file.txt"], &["my"]),
+        ("*.min.*", &["a.min.js", "x/b.min.css"], &["a.js"]),
+        ("/", &[], &["a", "a/b"]),
+    ];
+
+    #[test]
+    fn test_conformance() -> anyhow::Result<()> {
+        for (pattern, matching, non_matching) in CONFORMANCE {
+            let compiled = Pattern::new(pattern).map_err(|e| anyhow::anyhow!("{pattern}: {e}"))?;
+            for path in *matching {
+                assert!(compiled.matches(path), "{pattern} should match {path}");
+            }
+            for path in *non_matching {
+                assert!(!compiled.matches(path), "{pattern} shouldn't match {path}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_required_literal() -> anyhow::Result<()> {
+        let literal = |pattern| Pattern::new(pattern).map(|pattern| pattern.literal);
+        assert_eq!(literal("/src/billing/")?, Some("src/billing".into()));
+        assert_eq!(literal("**/docs/*.md")?, Some("docs".into()));
+        assert_eq!(literal("*.proto")?, Some(".proto".into()));
+        assert_eq!(literal("a/*/long/x?")?, Some("long/x".into()));
+        assert_eq!(literal("*")?, None);
+        assert_eq!(literal("/")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() {
+        let file = parse(
+            "# Comment\n\
+             \n\
+             *.rs @rust @org/team user@example.com # trailing comment\n\
+             !*.md @docs\n\
+             src/[ab].rs @a\n\
+             docs/ @docs not-an-owner @/team\n\
+             /vendor/\n\
+             \tdir\\ name/ @b\n",
+        );
+
+        let rules: Vec<_> = file
+            .rules
+            .iter()
+            .map(|rule| (rule.span, rule.pattern.as_str(), rule.owners.clone()))
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                (
+                    Span {
+                        line: 3,
+                        start: 0,
+                        end: 37
+                    },
+                    "*.rs",
+                    vec![
+                        Owner::from("@rust"),
+                        Owner::from("@org/team"),
+                        Owner::from("user@example.com")
+                    ]
+                ),
+                (
+                    Span {
+                        line: 7,
+                        start: 0,
+                        end: 8
+                    },
+                    "/vendor/",
+                    vec![]
+                ),
+                (
+                    Span {
+                        line: 8,
+                        start: 1,
+                        end: 14
+                    },
+                    "dir\\ name/",
+                    vec![Owner::from("@b")]
+                ),
+            ]
+        );
+
+        assert_eq!(
+            file.diagnostics,
+            vec![
+                Diagnostic {
+                    span: Span {
+                        line: 4,
+                        start: 0,
+                        end: 5
+                    },
+                    kind: DiagnosticKind::Negation
+                },
+                Diagnostic {
+                    span: Span {
+                        line: 5,
+                        start: 0,
+                        end: 11
+                    },
+                    kind: DiagnosticKind::CharacterRange
+                },
+                Diagnostic {
+                    span: Span {
+                        line: 6,
+                        start: 12,
+                        end: 24
+                    },
+                    kind: DiagnosticKind::InvalidOwner("not-an-owner".into())
+                },
+                Diagnostic {
+                    span: Span {
+                        line: 6,
+                        start: 25,
+                        end: 31
+                    },
+                    kind: DiagnosticKind::InvalidOwner("@/team".into())
+                },
+            ]
+        );
+        assert_eq!(
+            file.diagnostics[0].to_string(),
+            "`!` negation is not supported on line 4"
+        );
+    }
+
+    #[test]
+    fn test_sections() {
+        let file = parse(
+            "* @all\n\
+             [Backend] @org/backend\n\
+             /src/\n\
+             /src/ui/ @org/ui\n\
+             ^[Docs]\n\
+             *.md @docs\n\
+             [Security][2] @org/security\n\
+             /auth/\n\
+             [backend]\n\
+             /api/\n\
+             []\n\
+             [Junk]x\n\
+             [Count][0]\n",
+        );
+
+        let rules: Vec<_> = file
+            .rules
+            .iter()
+            .map(|rule| (rule.pattern.as_str(), rule.owners.clone(), rule.section))
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                ("*", vec![Owner::from("@all")], None),
+                ("/src/", vec![Owner::from("@org/backend")], Some(0)),
+                ("/src/ui/", vec![Owner::from("@org/ui")], Some(0)),
+                ("*.md", vec![Owner::from("@docs")], Some(1)),
+                ("/auth/", vec![Owner::from("@org/security")], Some(2)),
+                // Merged into the first `Backend` section
+                ("/api/", vec![Owner::from("@org/backend")], Some(0)),
+            ]
+        );
+
+        let sections: Vec<_> = file
+            .sections
+            .iter()
+            .map(|section| (section.name.as_str(), section.optional, section.approvals))
+            .collect();
+        assert_eq!(
+            sections,
+            vec![
+                ("Backend", false, 1),
+                ("Docs", true, 1),
+                ("Security", false, 2)
+            ]
+        );
+
+        let diagnostics: Vec<_> = file
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.span.line, diagnostic.kind.clone()))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (11, DiagnosticKind::InvalidSection),
+                (12, DiagnosticKind::InvalidSection),
+                (13, DiagnosticKind::InvalidSection),
+            ]
+        );
+    }
+}
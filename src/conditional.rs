@@ -1,62 +1,108 @@
-use codeowners::{Owner, Owners};
+use crate::{
+    owner::{Owner, TeamMembers},
+    owner_set::{OwnerInterner, OwnerSet},
+    rules::CodeownersRules,
+};
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{BTreeMap, HashSet},
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt::{self, Display},
+    str::FromStr,
 };
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Above this many candidate owners `SolveMode::Auto` skips the exact search
+/// and goes straight to the greedy heuristic.
+const EXACT_OWNER_LIMIT: usize = 64;
+/// Number of search nodes `SolveMode::Auto` is willing to visit before giving
+/// up on the exact search and falling back to the heuristic.
+const EXACT_NODE_BUDGET: usize = 200_000;
+/// Maximum number of equally small owner sets we report.
+const MAX_MINIMUM_SETS: usize = 5;
+/// Most clauses a single `AtLeast` is expanded into. The count comes from
+/// CODEOWNERS and the number of clauses grows combinatorially with it, so past
+/// this the solvers work from a stricter requirement instead.
+const MAX_THRESHOLD_CLAUSES: usize = 10_000;
+
+/// Serializes as `{"and": [...]}`, `{"or": [...]}`, `{"at_least": [n, [...]]}`
+/// or `{"owner": "@name"}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum OwnersConditional {
     And(Vec<OwnersConditional>),
     Or(Vec<OwnersConditional>),
-    Owner(String),
+    /// Satisfied once at least `n` of the items are. A team counts once for
+    /// every member that approves, so `AtLeast(2, [@org/security])` needs two
+    /// people from that team.
+    AtLeast(usize, Vec<OwnersConditional>),
+    Owner(Owner),
 }
 
 impl OwnersConditional {
-    pub fn from_codeowners(codeowners: &Owners, files: &[&str]) -> OwnersConditional {
-        let owners_map = to_owners_map(codeowners, files);
-        OwnersConditional::from_owners_map(owners_map)
+    /// The requirement for changing `files`. Rules in optional sections are
+    /// left out, since their reviews are only advisory.
+    pub fn from_codeowners(codeowners: &CodeownersRules, files: &[&str]) -> OwnersConditional {
+        let files: BTreeSet<&str> = files.iter().copied().collect();
+        let requirements: Vec<_> = files
+            .into_iter()
+            .flat_map(|file| codeowners.requirements_of(file))
+            .filter(|requirement| !requirement.is_optional())
+            .collect();
+        OwnersConditional::from_clauses(
+            requirements
+                .iter()
+                .map(|requirement| (&requirement.rule.owners[..], requirement.approvals)),
+        )
     }
 
-    fn from_owners_map(owners_map: BTreeMap<&str, Option<&Vec<Owner>>>) -> OwnersConditional {
-        let mut min_owners = OwnersConditional::And(vec![]);
-
-        for (_, owners) in owners_map.into_iter() {
-            let owners = match owners {
-                Some(owners) => owners,
-                None => {
-                    continue;
-                }
-            };
-            if owners.is_empty() {
-                continue;
-            }
+    /// Requires each clause, given as owners any `count` of which need to
+    /// approve.
+    fn from_clauses<'a, I>(clauses: I) -> OwnersConditional
+    where
+        I: IntoIterator<Item = (&'a [Owner], usize)>,
+    {
+        // Top level ownership is always an `And`
+        OwnersConditional::And(
+            clauses
+                .into_iter()
+                .filter_map(|(owners, count)| {
+                    OwnersConditional::from_owners_at_least(owners, count)
+                })
+                .collect(),
+        )
+    }
 
-            let new_item = if owners.len() == 1 {
-                OwnersConditional::Owner(format!("{}", owners[0]))
-            } else {
-                OwnersConditional::Or(
-                    owners
-                        .iter()
-                        .map(|owner| OwnersConditional::Owner(format!("{owner}")))
-                        .collect(),
-                )
-            };
-            // Top level ownership is always an `And`
-            min_owners = match min_owners {
-                OwnersConditional::And(mut and_items) => {
-                    and_items.push(new_item);
-                    OwnersConditional::And(and_items)
-                }
-                _ => {
-                    unreachable!()
-                }
-            };
+    /// Builds the clause for a single file owned by any of `owners`, or `None`
+    /// if there are no owners.
+    pub fn from_owners(owners: &[Owner]) -> Option<OwnersConditional> {
+        match owners {
+            [] => None,
+            [owner] => Some(OwnersConditional::Owner(owner.clone())),
+            owners => Some(OwnersConditional::Or(
+                owners
+                    .iter()
+                    .map(|owner| OwnersConditional::Owner(owner.clone()))
+                    .collect(),
+            )),
         }
+    }
 
-        min_owners
+    /// Like `from_owners`, but for a file that needs approvals from `count`
+    /// different owners.
+    pub fn from_owners_at_least(owners: &[Owner], count: usize) -> Option<OwnersConditional> {
+        if count <= 1 || owners.is_empty() {
+            return OwnersConditional::from_owners(owners);
+        }
+        Some(OwnersConditional::AtLeast(
+            count,
+            owners
+                .iter()
+                .map(|owner| OwnersConditional::Owner(owner.clone()))
+                .collect(),
+        ))
     }
 
-    pub fn remove_all(self, excluded_owners: &HashSet<String>) -> Option<OwnersConditional> {
+    pub fn remove_all(self, excluded_owners: &HashSet<Owner>) -> Option<OwnersConditional> {
         match self {
             OwnersConditional::And(owners) => {
                 let new_values: Vec<_> = owners
@@ -80,6 +126,23 @@ impl OwnersConditional {
                     _ => Some(OwnersConditional::Or(new_values)),
                 }
             }
+            OwnersConditional::AtLeast(count, owners) => {
+                let new_values: Vec<_> = owners
+                    .into_iter()
+                    .filter_map(|cond| cond.remove_all(excluded_owners))
+                    .collect();
+                if new_values.is_empty() {
+                    return None;
+                }
+                // Like an `Or` left with no owners, we can't ask for more
+                // approvals than there are owners left to give them
+                let count = if new_values.iter().any(OwnersConditional::is_team) {
+                    count
+                } else {
+                    count.min(new_values.len())
+                };
+                Some(OwnersConditional::AtLeast(count, new_values))
+            }
             OwnersConditional::Owner(owner) => {
                 if excluded_owners.contains(&owner) {
                     None
@@ -90,62 +153,620 @@ impl OwnersConditional {
         }
     }
 
-    pub fn reduce(self) -> OwnersConditional {
-        self.reduce_duplicates().reduce_or_duplicates()
+    /// Removes `owner` as an option everywhere. Unlike `remove_all`, anything
+    /// only `owner` could have satisfied is kept around as an unsatisfiable
+    /// empty `Or`, rather than dropped as if it wasn't needed.
+    pub fn remove_owner(self, owner: &Owner) -> OwnersConditional {
+        match self {
+            OwnersConditional::And(items) => OwnersConditional::And(
+                items
+                    .into_iter()
+                    .map(|item| item.remove_owner(owner))
+                    .collect(),
+            ),
+            OwnersConditional::Or(items) => OwnersConditional::Or(
+                items
+                    .into_iter()
+                    .filter(|item| !matches!(item, OwnersConditional::Owner(o) if o == owner))
+                    .map(|item| item.remove_owner(owner))
+                    .collect(),
+            ),
+            OwnersConditional::AtLeast(count, items) => OwnersConditional::AtLeast(
+                count,
+                items
+                    .into_iter()
+                    .filter(|item| !matches!(item, OwnersConditional::Owner(o) if o == owner))
+                    .map(|item| item.remove_owner(owner))
+                    .collect(),
+            ),
+            OwnersConditional::Owner(o) if o == *owner => OwnersConditional::Or(vec![]),
+            OwnersConditional::Owner(o) => OwnersConditional::Owner(o),
+        }
+    }
+
+    /// Whether any set of owners can satisfy this conditional. More approvals
+    /// never hurt, so this is whether everyone approving would.
+    pub fn is_satisfiable(&self) -> bool {
+        match self {
+            OwnersConditional::And(items) => items.iter().all(OwnersConditional::is_satisfiable),
+            OwnersConditional::Or(items) => items.iter().any(OwnersConditional::is_satisfiable),
+            OwnersConditional::AtLeast(count, items) => {
+                let satisfiable: Vec<_> =
+                    items.iter().filter(|item| item.is_satisfiable()).collect();
+                satisfiable.iter().any(|item| item.is_team()) || satisfiable.len() >= *count
+            }
+            OwnersConditional::Owner(_) => true,
+        }
+    }
+
+    /// Every team mentioned in this conditional.
+    pub fn teams(&self) -> BTreeSet<Owner> {
+        match self {
+            OwnersConditional::And(items)
+            | OwnersConditional::Or(items)
+            | OwnersConditional::AtLeast(_, items) => {
+                items.iter().flat_map(|item| item.teams()).collect()
+            }
+            OwnersConditional::Owner(owner) if owner.is_team() => [owner.clone()].into(),
+            OwnersConditional::Owner(_) => BTreeSet::new(),
+        }
+    }
+
+    /// Replaces every team with the individuals on it, so that the minimum
+    /// sets are made up of people and someone on several teams can cover all
+    /// of them at once. Teams with no known members are left as they are.
+    pub fn expand_teams(self, members: &TeamMembers) -> OwnersConditional {
+        match self {
+            OwnersConditional::And(items) => OwnersConditional::And(
+                items
+                    .into_iter()
+                    .map(|item| item.expand_teams(members))
+                    .collect(),
+            ),
+            OwnersConditional::Or(items) => OwnersConditional::Or(
+                items
+                    .into_iter()
+                    .map(|item| item.expand_teams(members))
+                    .collect(),
+            ),
+            // Each member of a team counts separately towards the threshold,
+            // so they are spliced in rather than grouped in an `Or`
+            OwnersConditional::AtLeast(count, items) => OwnersConditional::AtLeast(
+                count,
+                items
+                    .into_iter()
+                    .flat_map(|item| match &item {
+                        OwnersConditional::Owner(owner) => match members.members_of(owner) {
+                            Some(team_members) if !team_members.is_empty() => team_members
+                                .iter()
+                                .map(|member| OwnersConditional::Owner(member.clone()))
+                                .collect(),
+                            _ => vec![item],
+                        },
+                        _ => vec![item.expand_teams(members)],
+                    })
+                    .collect(),
+            ),
+            OwnersConditional::Owner(owner) => match members.members_of(&owner) {
+                Some(team_members) if !team_members.is_empty() => OwnersConditional::Or(
+                    team_members
+                        .iter()
+                        .map(|member| OwnersConditional::Owner(member.clone()))
+                        .collect(),
+                ),
+                _ => OwnersConditional::Owner(owner),
+            },
+        }
+    }
+
+    /// Checks which parts of this conditional are still outstanding given the
+    /// owners that have already approved.
+    pub fn evaluate(&self, approvals: &Approvals) -> Evaluation {
+        match self.residual(approvals) {
+            Some(residual) => Evaluation::Outstanding(residual.reduce()),
+            None => Evaluation::Satisfied,
+        }
+    }
+
+    /// Whether both conditionals are satisfied by exactly the same sets of
+    /// approvals, even if they are written differently. For example
+    /// `(a || b) && (a || c)` is equivalent to `a || (b && c)`.
+    pub fn is_equivalent(&self, other: &OwnersConditional) -> bool {
+        let (this, other) = (self.clone().reduce(), other.clone().reduce());
+        if this == other {
+            return true;
+        }
+        // A team can approve a threshold several times over, which the clauses
+        // can't express, and thresholds with too many clauses to list only have
+        // stricter ones, so those are only equivalent if they reduce the same
+        if this.has_team_threshold()
+            || other.has_team_threshold()
+            || this.has_capped_threshold()
+            || other.has_capped_threshold()
+        {
+            return false;
+        }
+        let canonical_clauses = |conditional: &OwnersConditional| {
+            let clauses = conditional.clauses();
+            let interner = OwnerInterner::new(clauses.iter().flatten());
+            absorb_clauses(
+                clauses
+                    .iter()
+                    .map(|clause| interner.set_of(clause))
+                    .collect(),
+            )
+            .iter()
+            .map(|clause| interner.owners_of(clause))
+            .collect::<BTreeSet<_>>()
+        };
+        canonical_clauses(&this) == canonical_clauses(&other)
+    }
+
+    /// Describes how the requirement changed going from `self` to `new`, as the
+    /// clauses that were added and removed. Empty if the two are equivalent.
+    pub fn diff(&self, new: &OwnersConditional) -> ConditionalDiff {
+        if self.is_equivalent(new) {
+            return ConditionalDiff::default();
+        }
+        let (old, new) = (self.clone().reduce(), new.clone().reduce());
+        let old_clauses: BTreeSet<_> = old.conjuncts().iter().collect();
+        let new_clauses: BTreeSet<_> = new.conjuncts().iter().collect();
+        ConditionalDiff {
+            added: new_clauses
+                .difference(&old_clauses)
+                .map(|clause| (*clause).clone())
+                .collect(),
+            removed: old_clauses
+                .difference(&new_clauses)
+                .map(|clause| (*clause).clone())
+                .collect(),
+        }
+    }
+
+    fn has_team_threshold(&self) -> bool {
+        match self {
+            OwnersConditional::And(items) | OwnersConditional::Or(items) => {
+                items.iter().any(OwnersConditional::has_team_threshold)
+            }
+            OwnersConditional::AtLeast(count, items) => {
+                (*count > 1 && items.iter().any(OwnersConditional::is_team))
+                    || items.iter().any(OwnersConditional::has_team_threshold)
+            }
+            OwnersConditional::Owner(_) => false,
+        }
+    }
+
+    /// Whether some `AtLeast` has more clauses than `MAX_THRESHOLD_CLAUSES`,
+    /// so that `clauses` only gives a stricter requirement.
+    fn has_capped_threshold(&self) -> bool {
+        match self {
+            OwnersConditional::And(items) | OwnersConditional::Or(items) => {
+                items.iter().any(OwnersConditional::has_capped_threshold)
+            }
+            OwnersConditional::AtLeast(count, items) => {
+                let others = items.iter().filter(|item| !item.is_team()).count();
+                let group_size = (others + 1).saturating_sub(*count);
+                binomial_at_most(others, group_size, MAX_THRESHOLD_CLAUSES).is_none()
+                    || items.iter().any(OwnersConditional::has_capped_threshold)
+            }
+            OwnersConditional::Owner(_) => false,
+        }
     }
 
-    fn reduce_duplicates(self) -> OwnersConditional {
+    /// The part of this conditional not yet satisfied by `approvals`, or
+    /// `None` if it is fully satisfied.
+    fn residual(&self, approvals: &Approvals) -> Option<OwnersConditional> {
         match self {
             OwnersConditional::And(items) => {
-                // Remove all duplicate items
-                let mut new_items = items;
-                for i in (0..new_items.len()).rev() {
-                    for j in 0..i {
-                        if new_items[j] == new_items[i] {
-                            new_items.remove(i);
-                            break;
+                let outstanding: Vec<_> = items
+                    .iter()
+                    .filter_map(|item| item.residual(approvals))
+                    .collect();
+                if outstanding.is_empty() {
+                    None
+                } else {
+                    Some(OwnersConditional::And(outstanding))
+                }
+            }
+            OwnersConditional::Or(items) => {
+                let outstanding = items
+                    .iter()
+                    .map(|item| item.residual(approvals))
+                    .collect::<Option<Vec<_>>>()?;
+                Some(OwnersConditional::Or(outstanding))
+            }
+            OwnersConditional::AtLeast(count, items) => {
+                let mut approved = 0;
+                let mut outstanding = vec![];
+                for item in items {
+                    match item {
+                        OwnersConditional::Owner(owner) if owner.is_team() => {
+                            approved += approvals.approvals_from(owner);
+                            // Other members of the team can still approve
+                            outstanding.push(item.clone());
                         }
+                        _ => match item.residual(approvals) {
+                            Some(residual) => outstanding.push(residual),
+                            None => approved += 1,
+                        },
                     }
                 }
-                OwnersConditional::And(new_items)
+                if approved >= *count {
+                    None
+                } else {
+                    Some(OwnersConditional::AtLeast(count - approved, outstanding))
+                }
             }
-            OwnersConditional::Or(items) => OwnersConditional::Or(items),
-            OwnersConditional::Owner(owner) => OwnersConditional::Owner(owner),
+            OwnersConditional::Owner(owner) => {
+                if approvals.contains(owner) {
+                    None
+                } else {
+                    Some(OwnersConditional::Owner(owner.clone()))
+                }
+            }
+        }
+    }
+
+    /// Returns the smallest sets of owners whose approval satisfies this
+    /// conditional. This is a hitting set problem over the clauses of the
+    /// conditional, so for very large PRs `SolveMode::Auto` falls back to a
+    /// greedy approximation.
+    pub fn minimum_owner_sets(&self, mode: SolveMode) -> MinimumOwnerSets {
+        let SolverClauses {
+            interner,
+            forced,
+            remaining,
+            exact: exact_clauses,
+        } = match self.solver_clauses() {
+            Some(clauses) => clauses,
+            None => {
+                return MinimumOwnerSets {
+                    sets: vec![],
+                    exact: true,
+                };
+            }
+        };
+
+        let solved = if use_exact_search(mode, &remaining) {
+            exact_hitting_sets(&remaining, budget_for(mode))
+        } else {
+            None
+        };
+        let (sets, exact) = match solved {
+            Some(sets) => (sets, exact_clauses),
+            None => (vec![greedy_hitting_set(&remaining, |_| 1)], false),
+        };
+
+        MinimumOwnerSets {
+            sets: sets
+                .into_iter()
+                .map(|set| interner.owners_of(&set.union(&forced)))
+                .collect(),
+            exact,
+        }
+    }
+
+    /// Returns the set of owners with the lowest total cost (as given by
+    /// `weight`) whose approval satisfies this conditional, or `None` if it
+    /// cannot be satisfied. Ties go to the set with fewer owners.
+    pub fn cheapest_owner_set<F>(&self, mode: SolveMode, weight: F) -> Option<WeightedOwnerSet>
+    where
+        F: Fn(&Owner) -> u32,
+    {
+        let SolverClauses {
+            interner,
+            forced,
+            remaining,
+            exact: exact_clauses,
+        } = self.solver_clauses()?;
+        let weights: Vec<u32> = (0..interner.len())
+            .map(|id| weight(interner.owner(id)))
+            .collect();
+
+        let solved = if use_exact_search(mode, &remaining) {
+            exact_cheapest_set(&remaining, &weights, budget_for(mode))
+        } else {
+            None
+        };
+        let (set, exact) = match solved {
+            Some(set) => (set, exact_clauses),
+            None => (greedy_hitting_set(&remaining, |id| weights[id]), false),
+        };
+
+        let owners: BTreeMap<Owner, u32> = set
+            .union(&forced)
+            .iter()
+            .map(|id| (interner.owner(id).clone(), weights[id]))
+            .collect();
+        Some(WeightedOwnerSet {
+            cost: owners.values().sum(),
+            owners,
+            exact,
+        })
+    }
+
+    /// Lists up to `count` different sets of owners that would each satisfy
+    /// this conditional, none of which contain an owner they could do without.
+    /// Sets are ranked by size, then by total cost as given by `weight` (pass
+    /// `|_| 0` to rank by size alone). For very large PRs this gives up early
+    /// and may return fewer sets than asked for.
+    pub fn alternative_owner_sets<F>(&self, count: usize, weight: F) -> Vec<WeightedOwnerSet>
+    where
+        F: Fn(&Owner) -> u32,
+    {
+        let SolverClauses {
+            interner,
+            forced,
+            remaining,
+            exact: exact_clauses,
+        } = match self.solver_clauses() {
+            Some(clauses) => clauses,
+            None => return vec![],
+        };
+        let to_weighted = |set: OwnerSet, exact: bool| {
+            let owners: BTreeMap<Owner, u32> = set
+                .union(&forced)
+                .iter()
+                .map(|id| {
+                    let owner = interner.owner(id);
+                    (owner.clone(), weight(owner))
+                })
+                .collect();
+            WeightedOwnerSet {
+                cost: owners.values().sum(),
+                owners,
+                exact,
+            }
+        };
+
+        let mut alternatives = vec![];
+        let mut nodes = 0;
+        for size in 0..=remaining.len() {
+            if alternatives.len() >= count {
+                break;
+            }
+            let mut found = BTreeSet::new();
+            let mut chosen = OwnerSet::default();
+            let completed = search_hitting_sets(
+                &remaining,
+                size,
+                &mut chosen,
+                &mut found,
+                &mut nodes,
+                Some(EXACT_NODE_BUDGET),
+            );
+            if !completed {
+                break;
+            }
+
+            // Smaller sets were already found on earlier iterations, and
+            // larger ones can come out of the search with redundant owners
+            let mut of_size: Vec<_> = found
+                .into_iter()
+                .filter(|set| set.len() == size && is_minimal_hitting_set(&remaining, set))
+                .map(|set| to_weighted(set, exact_clauses))
+                .collect();
+            of_size.sort_by(|a, b| a.cost.cmp(&b.cost).then_with(|| a.owners.cmp(&b.owners)));
+            alternatives.extend(of_size);
+        }
+
+        if alternatives.is_empty() && count > 0 {
+            alternatives.push(to_weighted(
+                greedy_hitting_set(&remaining, |id| weight(interner.owner(id))),
+                false,
+            ));
+        }
+        alternatives.truncate(count);
+        alternatives
+    }
+
+    /// Prepares the clauses for the set solvers. Returns `None` if some clause
+    /// cannot be satisfied at all.
+    fn solver_clauses(&self) -> Option<SolverClauses> {
+        let clauses = self.clauses();
+        if clauses.iter().any(|clause| clause.is_empty()) {
+            return None;
+        }
+        let interner = OwnerInterner::new(clauses.iter().flatten());
+        let clauses = absorb_clauses(
+            clauses
+                .iter()
+                .map(|clause| interner.set_of(clause))
+                .collect(),
+        );
+
+        let forced: OwnerSet = clauses
+            .iter()
+            .filter(|clause| clause.len() == 1)
+            .flat_map(OwnerSet::iter)
+            .collect();
+        let remaining = clauses
+            .into_iter()
+            .filter(|clause| clause.is_disjoint(&forced))
+            .collect();
+        Some(SolverClauses {
+            interner,
+            forced,
+            remaining,
+            exact: !self.has_capped_threshold(),
+        })
+    }
+
+    /// Flattens the conditional into conjunctive normal form, where each
+    /// element of the result is a set of owners, any of which is enough. An
+    /// `AtLeast` that would need more than `MAX_THRESHOLD_CLAUSES` clauses is
+    /// replaced with a stricter requirement, so every set of owners satisfying
+    /// the clauses still satisfies the conditional, but maybe not a minimal
+    /// one. `has_capped_threshold` says whether that happened.
+    fn clauses(&self) -> Vec<BTreeSet<Owner>> {
+        match self {
+            OwnersConditional::And(items) => items.iter().flat_map(|item| item.clauses()).collect(),
+            OwnersConditional::Or(items) => {
+                items.iter().fold(vec![BTreeSet::new()], |acc, item| {
+                    let item_clauses = item.clauses();
+                    acc.iter()
+                        .flat_map(|clause| {
+                            item_clauses.iter().map(move |item_clause| {
+                                clause.union(item_clause).cloned().collect()
+                            })
+                        })
+                        .collect()
+                })
+            }
+            // At least `n` of `m` items hold exactly when every group of
+            // `m - n + 1` of them has one that holds. A team can give any
+            // number of approvals on its own, so it goes in every group.
+            OwnersConditional::AtLeast(0, _) => vec![],
+            OwnersConditional::AtLeast(count, items) => {
+                let (teams, others): (Vec<_>, Vec<_>) =
+                    items.iter().cloned().partition(OwnersConditional::is_team);
+                let group_size = (others.len() + 1).saturating_sub(*count);
+                if binomial_at_most(others.len(), group_size, MAX_THRESHOLD_CLAUSES).is_none() {
+                    // Any of the teams, or the first `count` of the rest
+                    let mut stricter = teams;
+                    stricter.push(OwnersConditional::And(
+                        others.into_iter().take(*count).collect(),
+                    ));
+                    return OwnersConditional::Or(stricter).clauses();
+                }
+                combinations(&others, group_size)
+                    .into_iter()
+                    .flat_map(|mut group| {
+                        group.extend(teams.iter().cloned());
+                        OwnersConditional::Or(group).clauses()
+                    })
+                    .collect()
+            }
+            OwnersConditional::Owner(owner) => vec![[owner.clone()].into()],
         }
     }
 
-    fn reduce_or_duplicates(self) -> OwnersConditional {
+    /// Rewrites the conditional into a canonical normal form: nested `And`s
+    /// and `Or`s are flattened, duplicates and absorbed terms are dropped (so
+    /// `a && (a || b)` becomes `a`), single element groups are collapsed and
+    /// everything is sorted. Two conditionals that reduce to the same form
+    /// always display identically.
+    pub fn reduce(self) -> OwnersConditional {
         match self {
             OwnersConditional::And(items) => {
-                // Remove all duplicate items
-                let mut new_items = items;
-                for i in (0..new_items.len()).rev() {
-                    for j in 0..i {
-                        let remove = match (
-                            new_items.get(i).expect("index exists"),
-                            new_items.get(j).expect("index exists"),
-                        ) {
-                            (OwnersConditional::Or(items), OwnersConditional::Owner(owner)) => {
-                                items.contains(&OwnersConditional::Owner(owner.clone()))
-                            }
-                            (OwnersConditional::Owner(owner), OwnersConditional::Or(items)) => {
-                                items.contains(&OwnersConditional::Owner(owner.clone()))
-                            }
-                            _ => false,
-                        };
-
-                        if remove {
-                            new_items.remove(i);
-                            break;
-                        }
+                let items = Self::reduce_items(items, |item| match item {
+                    OwnersConditional::And(inner) => Ok(inner),
+                    other => Err(other),
+                });
+                let items = absorb_terms(items, OwnersConditional::disjuncts);
+                Self::collapse(items, OwnersConditional::And)
+            }
+            OwnersConditional::Or(items) => {
+                let items = Self::reduce_items(items, |item| match item {
+                    OwnersConditional::Or(inner) => Ok(inner),
+                    other => Err(other),
+                });
+                let items = absorb_terms(items, OwnersConditional::conjuncts);
+                Self::collapse(items, OwnersConditional::Or)
+            }
+            OwnersConditional::AtLeast(count, items) => {
+                let mut items: Vec<_> = items.into_iter().map(OwnersConditional::reduce).collect();
+                items.sort();
+                items.dedup();
+                let has_team = items.iter().any(OwnersConditional::is_team);
+                match count {
+                    0 => OwnersConditional::And(vec![]),
+                    1 => OwnersConditional::Or(items).reduce(),
+                    count if !has_team && count == items.len() => {
+                        OwnersConditional::And(items).reduce()
                     }
+                    count => OwnersConditional::AtLeast(count, items),
                 }
-                OwnersConditional::And(new_items)
             }
-            OwnersConditional::Or(items) => OwnersConditional::Or(items),
             OwnersConditional::Owner(owner) => OwnersConditional::Owner(owner),
         }
     }
+
+    /// Reduces every item, splices in the contents of any item of the same
+    /// kind as its parent (as picked out by `same_kind`), then sorts and
+    /// removes duplicates.
+    fn reduce_items<F>(items: Vec<OwnersConditional>, same_kind: F) -> Vec<OwnersConditional>
+    where
+        F: Fn(OwnersConditional) -> Result<Vec<OwnersConditional>, OwnersConditional>,
+    {
+        let mut new_items = vec![];
+        for item in items {
+            match same_kind(item.reduce()) {
+                Ok(inner) => new_items.extend(inner),
+                Err(other) => new_items.push(other),
+            }
+        }
+        new_items.sort();
+        new_items.dedup();
+        new_items
+    }
+
+    fn collapse<F>(mut items: Vec<OwnersConditional>, make: F) -> OwnersConditional
+    where
+        F: Fn(Vec<OwnersConditional>) -> OwnersConditional,
+    {
+        if items.len() == 1 {
+            items.remove(0)
+        } else {
+            make(items)
+        }
+    }
+
+    /// The terms of this conditional when read as an `Or`.
+    pub fn disjuncts(&self) -> &[OwnersConditional] {
+        match self {
+            OwnersConditional::Or(items) => items,
+            other => std::slice::from_ref(other),
+        }
+    }
+
+    /// The terms of this conditional when read as an `And`. For a reduced
+    /// conditional these are the independent clauses that all need to be
+    /// satisfied.
+    pub fn conjuncts(&self) -> &[OwnersConditional] {
+        match self {
+            OwnersConditional::And(items) => items,
+            other => std::slice::from_ref(other),
+        }
+    }
+
+    fn is_team(&self) -> bool {
+        matches!(self, OwnersConditional::Owner(owner) if owner.is_team())
+    }
+
+    /// Rank used to order the different kinds of conditional, so that owners
+    /// required on their own come before groups.
+    fn kind_rank(&self) -> u8 {
+        match self {
+            OwnersConditional::Owner(_) => 0,
+            OwnersConditional::Or(_) => 1,
+            OwnersConditional::And(_) => 2,
+            OwnersConditional::AtLeast(..) => 3,
+        }
+    }
+}
+
+impl Ord for OwnersConditional {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (OwnersConditional::Owner(a), OwnersConditional::Owner(b)) => a.cmp(b),
+            (OwnersConditional::And(a), OwnersConditional::And(b))
+            | (OwnersConditional::Or(a), OwnersConditional::Or(b)) => {
+                a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+            }
+            (OwnersConditional::AtLeast(n, a), OwnersConditional::AtLeast(m, b)) => n
+                .cmp(m)
+                .then_with(|| a.len().cmp(&b.len()))
+                .then_with(|| a.cmp(b)),
+            _ => self.kind_rank().cmp(&other.kind_rank()),
+        }
+    }
+}
+
+impl PartialOrd for OwnersConditional {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 impl Display for OwnersConditional {
@@ -159,6 +780,9 @@ impl Display for OwnersConditional {
                     .collect::<Vec<_>>()
                     .join(" && ")
             ),
+            // `()` is the empty `And`, which is always satisfied. The empty
+            // `Or` never is, so it needs a spelling of its own
+            OwnersConditional::Or(items) if items.is_empty() => "(||)".to_string(),
             OwnersConditional::Or(items) => format!(
                 "({})",
                 items
@@ -167,108 +791,908 @@ impl Display for OwnersConditional {
                     .collect::<Vec<_>>()
                     .join(" || ")
             ),
-            OwnersConditional::Owner(name) => name.clone(),
+            OwnersConditional::AtLeast(count, items) => match items.as_slice() {
+                [item] if !matches!(item, OwnersConditional::Or(_)) => {
+                    format!("{count} of {item}")
+                }
+                items => format!("{count} of {}", OwnersConditional::Or(items.to_vec())),
+            },
+            OwnersConditional::Owner(owner) => quote_owner(&owner.to_string()),
         };
         write!(f, "{text}")
     }
 }
 
-pub fn to_owners_map<'f, 'c>(
-    codeowners: &'c Owners,
-    files: &[&'f str],
-) -> BTreeMap<&'f str, Option<&'c Vec<Owner>>> {
-    files
-        .iter()
-        .map(|file| {
-            let owners = codeowners.of(*file);
-            (*file, owners)
-        })
-        .collect()
-}
+/// Parses the syntax written out by `Display`, e.g. `(@a && (@b || @org/c))`
+/// or `(@a && 2 of (@b || @c || @d))`. `()` is the empty `And` and `(||)` the
+/// empty `Or`.
+/// Owner names containing spaces, parentheses, `&`, `|` or quotes are written
+/// in double quotes with `\` escapes. Any reduced conditional parses back to
+/// itself.
+impl FromStr for OwnersConditional {
+    type Err = ParseError;
 
-#[cfg(test)]
-mod test {
-    use super::OwnersConditional;
-    use codeowners::Owner;
-    use std::collections::HashSet;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { input: s, pos: 0 };
+        let conditional = parser.parse_expression()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(conditional),
+            Some(c) => Err(parser.error(ParseErrorKind::Unexpected(c))),
+        }
+    }
+}
 
-    #[test]
-    fn test_from_owners() -> anyhow::Result<()> {
-        let owners_1 = vec![
-            Owner::Username("owner_a".into()),
-            Owner::Username("owner_b".into()),
-        ];
-        let owners_2 = vec![Owner::Username("owner_c".into())];
-        let owners_3 = vec![Owner::Username("owner_d".into())];
-        let owners_4 = vec![Owner::Username("owner_a".into())];
-
-        assert_eq!(
-            OwnersConditional::from_owners_map(
-                [
-                    ("a/file", Some(&owners_1)),
-                    ("b/file", Some(&owners_2)),
-                    ("d/file", Some(&owners_3)),
-                    ("e/file", Some(&owners_4)),
-                    ("w/file", None),
-                ]
-                .into_iter()
-                .collect(),
-            ),
-            OwnersConditional::And(vec![
-                OwnersConditional::Or(vec![
-                    OwnersConditional::Owner("owner_a".into()),
-                    OwnersConditional::Owner("owner_b".into())
-                ]),
-                OwnersConditional::Owner("owner_c".into()),
-                OwnersConditional::Owner("owner_d".into()),
-                OwnersConditional::Owner("owner_a".into()),
-            ])
-        );
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{kind} at offset {offset}")]
+pub struct ParseError {
+    /// Byte offset into the input where the error was found.
+    pub offset: usize,
+    pub kind: ParseErrorKind,
+}
 
-        Ok(())
-    }
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseErrorKind {
+    #[error("unexpected end of input")]
+    UnexpectedEnd,
+    #[error("unexpected character {0:?}")]
+    Unexpected(char),
+    #[error("expected an owner or '('")]
+    ExpectedOwner,
+    #[error("unclosed '('")]
+    UnclosedParen,
+    #[error("unterminated quoted owner")]
+    UnterminatedQuote,
+    #[error("cannot mix '&&' and '||' without parentheses")]
+    MixedOperators,
+}
 
-    #[test]
-    fn test_reduce() -> anyhow::Result<()> {
-        assert_eq!(
-            OwnersConditional::And(vec![
-                OwnersConditional::Owner("owner_c".into()),
-                OwnersConditional::Owner("owner_d".into()),
-                OwnersConditional::Owner("owner_c".into()),
-                OwnersConditional::Owner("owner_c".into()),
-                OwnersConditional::Owner("owner_e".into()),
-                OwnersConditional::Owner("owner_c".into()),
-            ])
-            .reduce(),
-            OwnersConditional::And(vec![
-                OwnersConditional::Owner("owner_c".into()),
-                OwnersConditional::Owner("owner_d".into()),
-                OwnersConditional::Owner("owner_e".into()),
-            ])
-        );
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    And,
+    Or,
+}
 
-        assert_eq!(
-            OwnersConditional::And(vec![
-                OwnersConditional::Owner("owner_c".into()),
-                OwnersConditional::Owner("owner_a".into()),
-                OwnersConditional::Or(vec![
-                    OwnersConditional::Owner("owner_c".into()),
-                    OwnersConditional::Owner("owner_e".into())
-                ]),
-            ])
-            .reduce(),
-            OwnersConditional::And(vec![
-                OwnersConditional::Owner("owner_c".into()),
-                OwnersConditional::Owner("owner_a".into())
-            ])
-        );
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    /// expression := term (operator term)*, with a single kind of operator
+    fn parse_expression(&mut self) -> Result<OwnersConditional, ParseError> {
+        let first = self.parse_term()?;
+        let mut items = vec![first];
+        let mut operator = None;
+        while let Some(next) = self.parse_operator()? {
+            if operator.is_some_and(|operator| operator != next) {
+                return Err(ParseError {
+                    offset: self.pos - 2,
+                    kind: ParseErrorKind::MixedOperators,
+                });
+            }
+            operator = Some(next);
+            items.push(self.parse_term()?);
+        }
+
+        Ok(match operator {
+            None => items.remove(0),
+            Some(Operator::And) => OwnersConditional::And(items),
+            Some(Operator::Or) => OwnersConditional::Or(items),
+        })
+    }
+
+    /// term := '(' expression? ')' | '(' '||' ')' | count 'of' term | owner
+    fn parse_term(&mut self) -> Result<OwnersConditional, ParseError> {
+        self.skip_whitespace();
+        if let Some((count, end)) = self.peek_threshold() {
+            self.pos = end;
+            // The items of `2 of (@a || @b)` are those of the `Or`
+            let items = match self.parse_term()? {
+                OwnersConditional::Or(items) => items,
+                other => vec![other],
+            };
+            return Ok(OwnersConditional::AtLeast(count, items));
+        }
+        match self.peek() {
+            Some('(') => {
+                let open = self.pos;
+                self.pos += 1;
+                self.skip_whitespace();
+                if self.peek() == Some(')') {
+                    self.pos += 1;
+                    return Ok(OwnersConditional::And(vec![]));
+                }
+                if let Some(rest) = self.input[self.pos..].strip_prefix("||")
+                    && let Some(after) = rest.trim_start().strip_prefix(')')
+                {
+                    self.pos = self.input.len() - after.len();
+                    return Ok(OwnersConditional::Or(vec![]));
+                }
+                let inner = self.parse_expression()?;
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(')') => {
+                        self.pos += 1;
+                        Ok(inner)
+                    }
+                    Some(c) => Err(self.error(ParseErrorKind::Unexpected(c))),
+                    None => Err(ParseError {
+                        offset: open,
+                        kind: ParseErrorKind::UnclosedParen,
+                    }),
+                }
+            }
+            Some('"') => self.parse_quoted_owner(),
+            Some(c) if is_name_char(c) => {
+                let start = self.pos;
+                while self.peek().is_some_and(is_name_char) {
+                    self.advance();
+                }
+                Ok(OwnersConditional::Owner(Owner::from(
+                    &self.input[start..self.pos],
+                )))
+            }
+            Some(_) => Err(self.error(ParseErrorKind::ExpectedOwner)),
+            None => Err(self.error(ParseErrorKind::UnexpectedEnd)),
+        }
+    }
+
+    /// Looks for `<count> of` at the current position. Returns the count and
+    /// the position right after `of`.
+    fn peek_threshold(&self) -> Option<(usize, usize)> {
+        let rest = &self.input[self.pos..];
+        let after_digits = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+        let count = rest[..rest.len() - after_digits.len()].parse().ok()?;
+        let after_of = after_digits
+            .strip_prefix(char::is_whitespace)?
+            .trim_start()
+            .strip_prefix("of")?;
+        if !after_of.starts_with(|c: char| c.is_whitespace() || c == '(' || c == '"') {
+            return None;
+        }
+        Some((count, self.input.len() - after_of.len()))
+    }
+
+    fn parse_quoted_owner(&mut self) -> Result<OwnersConditional, ParseError> {
+        let open = self.pos;
+        self.pos += 1;
+        let mut name = String::new();
+        loop {
+            match self.advance() {
+                Some('"') => return Ok(OwnersConditional::Owner(Owner::from(name))),
+                Some('\\') => match self.advance() {
+                    Some(c) => name.push(c),
+                    None => break,
+                },
+                Some(c) => name.push(c),
+                None => break,
+            }
+        }
+        Err(ParseError {
+            offset: open,
+            kind: ParseErrorKind::UnterminatedQuote,
+        })
+    }
+
+    fn parse_operator(&mut self) -> Result<Option<Operator>, ParseError> {
+        self.skip_whitespace();
+        let operator = match self.peek() {
+            Some('&') => Operator::And,
+            Some('|') => Operator::Or,
+            _ => return Ok(None),
+        };
+        let symbol = if operator == Operator::And {
+            "&&"
+        } else {
+            "||"
+        };
+        if !self.input[self.pos..].starts_with(symbol) {
+            let c = self.peek().expect("just peeked an operator");
+            return Err(self.error(ParseErrorKind::Unexpected(c)));
+        }
+        self.pos += 2;
+        Ok(Some(operator))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.advance();
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            offset: self.pos,
+            kind,
+        }
+    }
+}
+
+/// Characters that can appear in an owner name without quoting it.
+fn is_name_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '(' | ')' | '&' | '|' | '"' | '\\')
+}
+
+fn quote_owner(name: &str) -> String {
+    if !name.is_empty() && name.chars().all(is_name_char) {
+        return name.to_string();
+    }
+    let mut quoted = "\"".to_string();
+    for c in name.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The owners that have approved a PR.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Approvals {
+    pub users: HashSet<Owner>,
+    /// Teams count as approved if any of their members approved.
+    pub teams: HashSet<Owner>,
+    /// How many members of each team approved, where known. Teams missing
+    /// from here count as a single approval if they're in `teams`.
+    pub team_approvals: HashMap<Owner, usize>,
+}
+
+impl Approvals {
+    /// Builds approvals from GitHub logins, with no team information.
+    pub fn from_logins<I, S>(logins: I) -> Approvals
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Approvals {
+            users: logins
+                .into_iter()
+                .map(|login| Owner::User(login.as_ref().to_string()))
+                .collect(),
+            teams: HashSet::new(),
+            team_approvals: HashMap::new(),
+        }
+    }
+
+    /// Also counts the approval of every team an approving user is on.
+    pub fn with_team_members(mut self, members: &TeamMembers) -> Approvals {
+        let teams: Vec<_> = self
+            .users
+            .iter()
+            .flat_map(|user| members.teams_of(user))
+            .collect();
+        for team in teams {
+            *self.team_approvals.entry(team.clone()).or_default() += 1;
+            self.teams.insert(team);
+        }
+        self
+    }
+
+    pub fn contains(&self, owner: &Owner) -> bool {
+        self.users.contains(owner) || self.teams.contains(owner)
+    }
+
+    /// How many approvals `owner` accounts for. Only teams can have more
+    /// than one.
+    pub fn approvals_from(&self, owner: &Owner) -> usize {
+        match self.team_approvals.get(owner) {
+            Some(count) => *count,
+            None => usize::from(self.contains(owner)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Evaluation {
+    Satisfied,
+    /// Holds the reduced conditional made up of only the outstanding parts.
+    Outstanding(OwnersConditional),
+}
+
+/// The difference between two requirements, as returned by
+/// `OwnersConditional::diff`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConditionalDiff {
+    /// Clauses of the new requirement that the old one didn't have.
+    pub added: Vec<OwnersConditional>,
+    /// Clauses of the old requirement that the new one doesn't have.
+    pub removed: Vec<OwnersConditional>,
+}
+
+impl ConditionalDiff {
+    /// True if the requirement didn't meaningfully change.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SolveMode {
+    /// Search exhaustively unless the problem is too large, in which case use
+    /// the heuristic.
+    #[default]
+    Auto,
+    /// Always search exhaustively, however long it takes.
+    Exact,
+    /// Always use the greedy heuristic.
+    Heuristic,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MinimumOwnerSets {
+    /// Every set here has the same size. Empty if the conditional cannot be
+    /// satisfied at all.
+    pub sets: Vec<BTreeSet<Owner>>,
+    /// Whether the sets are guaranteed to be minimal, or just a heuristic
+    /// approximation.
+    pub exact: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WeightedOwnerSet {
+    /// The chosen owners, along with the cost of each.
+    pub owners: BTreeMap<Owner, u32>,
+    /// Sum of the costs of all chosen owners.
+    pub cost: u32,
+    /// Whether the set is guaranteed to be the cheapest, or just a heuristic
+    /// approximation.
+    pub exact: bool,
+}
+
+impl Display for MinimumOwnerSets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = self
+            .sets
+            .iter()
+            .map(|set| {
+                format!(
+                    "{{{}}}",
+                    set.iter()
+                        .map(|owner| owner.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" or ");
+        write!(f, "{text}")
+    }
+}
+
+/// Drops every term whose sub-terms (as given by `terms`) are a superset of
+/// some other term's, e.g. the `(a || b)` in `a && (a || b)`. Expects `items`
+/// to already be free of duplicates. Any term that can absorb another shares
+/// its first sub-term with it, so only those pairs get compared.
+fn absorb_terms<F>(items: Vec<OwnersConditional>, terms: F) -> Vec<OwnersConditional>
+where
+    F: Fn(&OwnersConditional) -> &[OwnersConditional],
+{
+    // Items with no sub-terms at all absorb everything else
+    let mut absorb_all = vec![];
+    let mut by_first: BTreeMap<&OwnersConditional, Vec<usize>> = BTreeMap::new();
+    for (idx, item) in items.iter().enumerate() {
+        match terms(item).first() {
+            Some(first) => by_first.entry(first).or_default().push(idx),
+            None => absorb_all.push(idx),
+        }
+    }
+
+    let absorbed: Vec<bool> = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let item_terms = terms(item);
+            item_terms
+                .iter()
+                .filter_map(|term| by_first.get(term))
+                .flatten()
+                .chain(&absorb_all)
+                .any(|&j| {
+                    i != j
+                        && terms(&items[j])
+                            .iter()
+                            .all(|other_term| item_terms.contains(other_term))
+                })
+        })
+        .collect();
+    items
+        .into_iter()
+        .zip(absorbed)
+        .filter_map(|(item, absorbed)| (!absorbed).then_some(item))
+        .collect()
+}
+
+/// The number of ways of picking `size` out of `count` items, or `None` if
+/// that's more than `limit`.
+fn binomial_at_most(count: usize, size: usize, limit: usize) -> Option<usize> {
+    let Some(rest) = count.checked_sub(size) else {
+        return Some(0);
+    };
+    let size = size.min(rest);
+    let mut ways = 1usize;
+    // Each step gives the number of ways of picking `i + 1` items, which only
+    // grows up to picking half of them
+    for i in 0..size {
+        ways = ways.checked_mul(count - i)? / (i + 1);
+        if ways > limit {
+            return None;
+        }
+    }
+    Some(ways)
+}
+
+/// Every way of picking `size` items out of `items`, keeping their order.
+fn combinations<T: Clone>(items: &[T], size: usize) -> Vec<Vec<T>> {
+    if size == 0 {
+        return vec![vec![]];
+    }
+    if items.len() < size {
+        return vec![];
+    }
+    let mut with_first = combinations(&items[1..], size - 1);
+    for combination in &mut with_first {
+        combination.insert(0, items[0].clone());
+    }
+    with_first.extend(combinations(&items[1..], size));
+    with_first
+}
+
+/// The clauses of a conditional, as sets of interned owners ready for the set
+/// solvers.
+struct SolverClauses {
+    interner: OwnerInterner,
+    /// Owners that are the only option for some clause, and so are part of
+    /// every solution.
+    forced: OwnerSet,
+    /// The clauses that the forced owners do not already satisfy.
+    remaining: Vec<OwnerSet>,
+    /// False if the clauses are stricter than the conditional, in which case
+    /// no solution can be known to be the smallest.
+    exact: bool,
+}
+
+/// Drops duplicate clauses and any clause that is a superset of another one,
+/// since satisfying the smaller clause always satisfies the larger one. Only
+/// clauses that share their first owner with a larger one need to be compared
+/// against it, which keeps this close to linear for typical PRs.
+fn absorb_clauses(mut clauses: Vec<OwnerSet>) -> Vec<OwnerSet> {
+    clauses.sort_by_key(|clause| clause.len());
+    let mut seen = HashSet::new();
+    let mut kept: Vec<OwnerSet> = vec![];
+    // Indices into `kept`, keyed by the first owner of each clause
+    let mut by_first: HashMap<usize, Vec<usize>> = HashMap::new();
+    for clause in clauses {
+        if !seen.insert(clause.clone()) {
+            continue;
+        }
+        let absorbed = clause.iter().any(|id| {
+            by_first.get(&id).is_some_and(|smaller| {
+                smaller
+                    .iter()
+                    .any(|&smaller| kept[smaller].is_subset(&clause))
+            })
+        });
+        if !absorbed {
+            if let Some(first) = clause.first() {
+                by_first.entry(first).or_default().push(kept.len());
+            }
+            kept.push(clause);
+        }
+    }
+    kept
+}
+
+fn use_exact_search(mode: SolveMode, clauses: &[OwnerSet]) -> bool {
+    match mode {
+        SolveMode::Exact => true,
+        SolveMode::Heuristic => false,
+        SolveMode::Auto => {
+            let owners = clauses
+                .iter()
+                .fold(OwnerSet::default(), |owners, clause| owners.union(clause));
+            owners.len() <= EXACT_OWNER_LIMIT
+        }
+    }
+}
+
+fn budget_for(mode: SolveMode) -> Option<usize> {
+    match mode {
+        SolveMode::Auto => Some(EXACT_NODE_BUDGET),
+        _ => None,
+    }
+}
+
+/// Finds every minimum hitting set of `clauses` (up to `MAX_MINIMUM_SETS`) by
+/// iterative deepening on the set size. Returns `None` if `budget` search
+/// nodes were visited without finishing.
+fn exact_hitting_sets(clauses: &[OwnerSet], budget: Option<usize>) -> Option<Vec<OwnerSet>> {
+    let mut nodes = 0;
+    for size in 0..=clauses.len() {
+        let mut found = BTreeSet::new();
+        let mut chosen = OwnerSet::default();
+        if !search_hitting_sets(clauses, size, &mut chosen, &mut found, &mut nodes, budget) {
+            return None;
+        }
+        if !found.is_empty() {
+            return Some(found.into_iter().take(MAX_MINIMUM_SETS).collect());
+        }
+    }
+    unreachable!("picking one owner per clause always satisfies every clause")
+}
+
+/// Whether every owner in `set` is needed to satisfy all the `clauses`.
+fn is_minimal_hitting_set(clauses: &[OwnerSet], set: &OwnerSet) -> bool {
+    set.iter().all(|owner| {
+        clauses
+            .iter()
+            .any(|clause| clause.contains(owner) && clause.intersection_len(set) == 1)
+    })
+}
+
+/// Depth first search for hitting sets of exactly `size` owners. Always
+/// branches on the smallest clause not yet satisfied, so every minimum set is
+/// reachable. Returns `false` if the node budget ran out.
+fn search_hitting_sets(
+    clauses: &[OwnerSet],
+    size: usize,
+    chosen: &mut OwnerSet,
+    found: &mut BTreeSet<OwnerSet>,
+    nodes: &mut usize,
+    budget: Option<usize>,
+) -> bool {
+    *nodes += 1;
+    if budget.is_some_and(|budget| *nodes > budget) {
+        return false;
+    }
+
+    let unsatisfied = clauses
+        .iter()
+        .filter(|clause| clause.is_disjoint(chosen))
+        .min_by_key(|clause| clause.len());
+    let clause = match unsatisfied {
+        Some(clause) => clause,
+        None => {
+            found.insert(chosen.clone());
+            return true;
+        }
+    };
+    if chosen.len() == size {
+        return true;
+    }
+
+    for owner in clause.iter() {
+        chosen.insert(owner);
+        let completed = search_hitting_sets(clauses, size, chosen, found, nodes, budget);
+        chosen.remove(owner);
+        if !completed {
+            return false;
+        }
+    }
+    true
+}
+
+/// Branch and bound search for the cheapest hitting set of `clauses`, where
+/// `weights` holds the cost of each owner id. Returns `None` if `budget` search
+/// nodes were visited without finishing.
+fn exact_cheapest_set(
+    clauses: &[OwnerSet],
+    weights: &[u32],
+    budget: Option<usize>,
+) -> Option<OwnerSet> {
+    let mut best = None;
+    let mut nodes = 0;
+    let mut chosen = OwnerSet::default();
+    if search_cheapest_set(
+        clauses,
+        weights,
+        &mut chosen,
+        0,
+        &mut best,
+        &mut nodes,
+        budget,
+    ) {
+        best.map(|(_, set)| set)
+    } else {
+        None
+    }
+}
+
+/// Depth first search over the same branches as `search_hitting_sets`,
+/// pruning any branch that already costs at least as much as the best set
+/// found so far. Returns `false` if the node budget ran out.
+fn search_cheapest_set(
+    clauses: &[OwnerSet],
+    weights: &[u32],
+    chosen: &mut OwnerSet,
+    cost: u32,
+    best: &mut Option<(u32, OwnerSet)>,
+    nodes: &mut usize,
+    budget: Option<usize>,
+) -> bool {
+    *nodes += 1;
+    if budget.is_some_and(|budget| *nodes > budget) {
+        return false;
+    }
+    if let Some((best_cost, best_set)) = best
+        && (cost, chosen.len()) >= (*best_cost, best_set.len())
+    {
+        return true;
+    }
+
+    let unsatisfied = clauses
+        .iter()
+        .filter(|clause| clause.is_disjoint(chosen))
+        .min_by_key(|clause| clause.len());
+    let clause = match unsatisfied {
+        Some(clause) => clause,
+        None => {
+            *best = Some((cost, chosen.clone()));
+            return true;
+        }
+    };
+
+    for owner in clause.iter() {
+        chosen.insert(owner);
+        let completed = search_cheapest_set(
+            clauses,
+            weights,
+            chosen,
+            cost + weights[owner],
+            best,
+            nodes,
+            budget,
+        );
+        chosen.remove(owner);
+        if !completed {
+            return false;
+        }
+    }
+    true
+}
+
+/// Repeatedly picks the owner with the lowest cost per newly satisfied clause,
+/// then drops any owner that turned out to be redundant, most expensive first.
+fn greedy_hitting_set<F>(clauses: &[OwnerSet], weight: F) -> OwnerSet
+where
+    F: Fn(usize) -> u32,
+{
+    let mut chosen = OwnerSet::default();
+    let mut unsatisfied: Vec<_> = clauses.iter().collect();
+    while !unsatisfied.is_empty() {
+        let mut counts = BTreeMap::<usize, u64>::new();
+        for owner in unsatisfied.iter().flat_map(|clause| clause.iter()) {
+            *counts.entry(owner).or_default() += 1;
+        }
+        // Compare weight / count without dividing. Ties go to the
+        // alphabetically first owner, which has the smallest id, to keep
+        // output stable.
+        let (best, _) = counts
+            .into_iter()
+            .min_by(|(a, a_count), (b, b_count)| {
+                (u64::from(weight(*a)) * b_count)
+                    .cmp(&(u64::from(weight(*b)) * a_count))
+                    .then_with(|| a.cmp(b))
+            })
+            .expect("unsatisfied clauses are never empty");
+        chosen.insert(best);
+        unsatisfied.retain(|clause| !clause.contains(best));
+    }
+
+    let mut by_weight: Vec<_> = chosen.iter().collect();
+    by_weight.sort_by_key(|owner| std::cmp::Reverse(weight(*owner)));
+    for owner in by_weight {
+        chosen.remove(owner);
+        if clauses.iter().any(|clause| clause.is_disjoint(&chosen)) {
+            chosen.insert(owner);
+        }
+    }
+    chosen
+}
+
+/// Everyone listed as an owner of each file, across all sections. `None` for
+/// files no rule matches.
+pub fn to_owners_map<'f>(
+    codeowners: &CodeownersRules,
+    files: &[&'f str],
+) -> BTreeMap<&'f str, Option<Vec<Owner>>> {
+    files
+        .iter()
+        .map(|file| {
+            let owners = codeowners.owners_of(file);
+            (*file, owners)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        Approvals, ConditionalDiff, Evaluation, MinimumOwnerSets, OwnersConditional, ParseError,
+        ParseErrorKind, SolveMode, WeightedOwnerSet, binomial_at_most,
+    };
+    use crate::{
+        owner::{Owner, TeamMembers},
+        rules::CodeownersRules,
+    };
+    use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+    #[test]
+    fn test_from_owners() -> anyhow::Result<()> {
+        let owners_1 = [Owner::User("owner_a".into()), Owner::User("owner_b".into())];
+        let owners_2 = [Owner::User("owner_c".into())];
+        let owners_3 = [Owner::User("owner_d".into())];
+        let owners_4 = [Owner::User("owner_a".into())];
+
+        assert_eq!(
+            OwnersConditional::from_clauses([
+                (&owners_1[..], 1),
+                (&owners_2[..], 1),
+                (&owners_3[..], 1),
+                (&owners_4[..], 1),
+                (&[][..], 1),
+            ]),
+            OwnersConditional::And(vec![
+                OwnersConditional::Or(vec![
+                    OwnersConditional::Owner("owner_a".into()),
+                    OwnersConditional::Owner("owner_b".into())
+                ]),
+                OwnersConditional::Owner("owner_c".into()),
+                OwnersConditional::Owner("owner_d".into()),
+                OwnersConditional::Owner("owner_a".into()),
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reduce() -> anyhow::Result<()> {
+        assert_eq!(
+            OwnersConditional::And(vec![
+                OwnersConditional::Owner("owner_c".into()),
+                OwnersConditional::Owner("owner_d".into()),
+                OwnersConditional::Owner("owner_c".into()),
+                OwnersConditional::Owner("owner_c".into()),
+                OwnersConditional::Owner("owner_e".into()),
+                OwnersConditional::Owner("owner_c".into()),
+            ])
+            .reduce(),
+            OwnersConditional::And(vec![
+                OwnersConditional::Owner("owner_c".into()),
+                OwnersConditional::Owner("owner_d".into()),
+                OwnersConditional::Owner("owner_e".into()),
+            ])
+        );
+
+        assert_eq!(
+            OwnersConditional::And(vec![
+                OwnersConditional::Owner("owner_c".into()),
+                OwnersConditional::Owner("owner_a".into()),
+                OwnersConditional::Or(vec![
+                    OwnersConditional::Owner("owner_c".into()),
+                    OwnersConditional::Owner("owner_e".into())
+                ]),
+            ])
+            .reduce(),
+            OwnersConditional::And(vec![
+                OwnersConditional::Owner("owner_a".into()),
+                OwnersConditional::Owner("owner_c".into())
+            ])
+        );
+
+        // Nested groups are flattened and absorbed, regardless of order
+        assert_eq!(
+            OwnersConditional::And(vec![
+                OwnersConditional::Or(vec![
+                    OwnersConditional::Owner("owner_c".into()),
+                    OwnersConditional::Owner("owner_b".into()),
+                    OwnersConditional::Owner("owner_a".into()),
+                ]),
+                OwnersConditional::And(vec![
+                    OwnersConditional::Owner("owner_d".into()),
+                    OwnersConditional::Or(vec![
+                        OwnersConditional::Owner("owner_b".into()),
+                        OwnersConditional::Or(vec![OwnersConditional::Owner("owner_a".into())]),
+                    ]),
+                ]),
+            ])
+            .reduce(),
+            OwnersConditional::And(vec![
+                OwnersConditional::Owner("owner_d".into()),
+                OwnersConditional::Or(vec![
+                    OwnersConditional::Owner("owner_a".into()),
+                    OwnersConditional::Owner("owner_b".into()),
+                ]),
+            ])
+        );
+
+        // The same absorption applies to `And` terms inside an `Or`
+        assert_eq!(
+            OwnersConditional::Or(vec![
+                OwnersConditional::And(vec![
+                    OwnersConditional::Owner("owner_b".into()),
+                    OwnersConditional::Owner("owner_a".into()),
+                ]),
+                OwnersConditional::Owner("owner_a".into()),
+                OwnersConditional::Or(vec![OwnersConditional::Owner("owner_c".into())]),
+            ])
+            .reduce(),
+            OwnersConditional::Or(vec![
+                OwnersConditional::Owner("owner_a".into()),
+                OwnersConditional::Owner("owner_c".into()),
+            ])
+        );
+
+        // Single element groups collapse into their only element
+        assert_eq!(
+            OwnersConditional::And(vec![OwnersConditional::Or(vec![OwnersConditional::Owner(
+                "owner_a".into()
+            )])])
+            .reduce(),
+            OwnersConditional::Owner("owner_a".into())
+        );
+        assert_eq!(
+            OwnersConditional::And(vec![]).reduce(),
+            OwnersConditional::And(vec![])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reduce_canonical() -> anyhow::Result<()> {
+        let clause_ab = OwnersConditional::Or(vec![
+            OwnersConditional::Owner("owner_a".into()),
+            OwnersConditional::Owner("owner_b".into()),
+        ]);
+        let clause_ba = OwnersConditional::Or(vec![
+            OwnersConditional::Owner("owner_b".into()),
+            OwnersConditional::Owner("owner_a".into()),
+        ]);
+        let clause_cd = OwnersConditional::Or(vec![
+            OwnersConditional::Owner("owner_d".into()),
+            OwnersConditional::Owner("owner_c".into()),
+        ]);
+
+        let first = OwnersConditional::And(vec![
+            clause_ab.clone(),
+            OwnersConditional::Owner("owner_e".into()),
+            clause_cd.clone(),
+        ])
+        .reduce();
+        let second = OwnersConditional::And(vec![
+            clause_cd,
+            clause_ba,
+            OwnersConditional::Owner("owner_e".into()),
+            clause_ab,
+        ])
+        .reduce();
+        assert_eq!(first, second);
+        assert_eq!(
+            format!("{first}"),
+            "(@owner_e && (@owner_a || @owner_b) && (@owner_c || @owner_d))"
+        );
+        assert_eq!(first.clone().reduce(), first);
 
         Ok(())
     }
 
     #[test]
     fn test_remove() -> anyhow::Result<()> {
-        let exclude_owners: HashSet<String> = ["owner_a".into(), "owner_d".into()].into();
+        let exclude_owners: HashSet<Owner> = ["owner_a".into(), "owner_d".into()].into();
         assert_eq!(
             OwnersConditional::And(vec![
                 OwnersConditional::Owner("owner_a".into()),
@@ -333,9 +1757,9 @@ mod test {
             .ok_or_else(|| anyhow::anyhow!("remove_all gave empty owners"))?
             .reduce(),
             OwnersConditional::And(vec![
-                OwnersConditional::Owner("owner_e".into()),
                 OwnersConditional::Owner("owner_b".into()),
                 OwnersConditional::Owner("owner_c".into()),
+                OwnersConditional::Owner("owner_e".into()),
             ])
         );
 
@@ -348,6 +1772,608 @@ mod test {
             None,
         );
 
+        // Removing a single owner keeps track of what nobody else can satisfy
+        let author = Owner::from("owner_a");
+        let removed = parse("@owner_a && (@owner_a || @owner_b)").remove_owner(&author);
+        assert!(!removed.is_satisfiable());
+        assert_eq!(
+            removed.reduce().minimum_owner_sets(SolveMode::Exact).sets,
+            Vec::<BTreeSet<Owner>>::new()
+        );
+        let removed = parse("2 of (@owner_a || @owner_b || @owner_c)").remove_owner(&author);
+        assert_eq!(removed.reduce(), parse("@owner_b && @owner_c"));
+        assert!(
+            !parse("2 of (@owner_a || @owner_b)")
+                .remove_owner(&author)
+                .is_satisfiable()
+        );
+
+        Ok(())
+    }
+
+    fn owner_set(owners: &[&str]) -> BTreeSet<Owner> {
+        owners.iter().map(|owner| Owner::from(*owner)).collect()
+    }
+
+    #[test]
+    fn test_minimum_owner_sets() -> anyhow::Result<()> {
+        // (a || b) && (b || c) && (c || d): b and c together cover everything
+        let conditional = OwnersConditional::And(vec![
+            OwnersConditional::Or(vec![
+                OwnersConditional::Owner("owner_a".into()),
+                OwnersConditional::Owner("owner_b".into()),
+            ]),
+            OwnersConditional::Or(vec![
+                OwnersConditional::Owner("owner_b".into()),
+                OwnersConditional::Owner("owner_c".into()),
+            ]),
+            OwnersConditional::Or(vec![
+                OwnersConditional::Owner("owner_c".into()),
+                OwnersConditional::Owner("owner_d".into()),
+            ]),
+        ]);
+        assert_eq!(
+            conditional.minimum_owner_sets(SolveMode::Exact),
+            MinimumOwnerSets {
+                sets: vec![
+                    owner_set(&["owner_a", "owner_c"]),
+                    owner_set(&["owner_b", "owner_c"]),
+                    owner_set(&["owner_b", "owner_d"]),
+                ],
+                exact: true,
+            }
+        );
+        assert_eq!(
+            conditional.minimum_owner_sets(SolveMode::Auto),
+            conditional.minimum_owner_sets(SolveMode::Exact),
+        );
+
+        // A required owner is included in every set
+        assert_eq!(
+            OwnersConditional::And(vec![
+                OwnersConditional::Owner("owner_e".into()),
+                OwnersConditional::Or(vec![
+                    OwnersConditional::Owner("owner_a".into()),
+                    OwnersConditional::Owner("owner_e".into()),
+                ]),
+                OwnersConditional::Or(vec![
+                    OwnersConditional::Owner("owner_b".into()),
+                    OwnersConditional::Owner("owner_c".into()),
+                ]),
+            ])
+            .minimum_owner_sets(SolveMode::Exact)
+            .sets,
+            vec![
+                owner_set(&["owner_b", "owner_e"]),
+                owner_set(&["owner_c", "owner_e"]),
+            ]
+        );
+
+        assert_eq!(
+            OwnersConditional::And(vec![]).minimum_owner_sets(SolveMode::Auto),
+            MinimumOwnerSets {
+                sets: vec![BTreeSet::new()],
+                exact: true,
+            }
+        );
+        assert_eq!(
+            OwnersConditional::Or(vec![])
+                .minimum_owner_sets(SolveMode::Auto)
+                .sets,
+            Vec::<BTreeSet<Owner>>::new()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_minimum_owner_sets_heuristic() -> anyhow::Result<()> {
+        // The classic case greedy gets wrong: owner_t1 satisfies the most
+        // clauses, but owner_s1 and owner_s2 together are enough. Each clause
+        // has its own extra owner so none of them are merged away.
+        let clause = |idx: usize, owners: &[&str]| {
+            let mut items: Vec<_> = owners
+                .iter()
+                .map(|owner| OwnersConditional::Owner((*owner).into()))
+                .collect();
+            items.push(OwnersConditional::Owner(format!("extra_{idx}").into()));
+            OwnersConditional::Or(items)
+        };
+        let conditional = OwnersConditional::And(
+            (0..14)
+                .map(|idx| match idx {
+                    0..=3 => clause(idx, &["owner_s1", "owner_t1"]),
+                    4..=5 => clause(idx, &["owner_s1", "owner_t2"]),
+                    6 => clause(idx, &["owner_s1", "owner_t3"]),
+                    7..=10 => clause(idx, &["owner_s2", "owner_t1"]),
+                    11..=12 => clause(idx, &["owner_s2", "owner_t2"]),
+                    _ => clause(idx, &["owner_s2", "owner_t3"]),
+                })
+                .collect(),
+        );
+
+        assert_eq!(
+            conditional.minimum_owner_sets(SolveMode::Exact),
+            MinimumOwnerSets {
+                sets: vec![owner_set(&["owner_s1", "owner_s2"])],
+                exact: true,
+            }
+        );
+        assert_eq!(
+            conditional.minimum_owner_sets(SolveMode::Heuristic),
+            MinimumOwnerSets {
+                sets: vec![owner_set(&["owner_t1", "owner_t2", "owner_t3"])],
+                exact: false,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cheapest_owner_set() -> anyhow::Result<()> {
+        // (a || @org/platform) && (b || @org/platform): the team alone is the
+        // smallest set, but it's expensive enough that a and b are cheaper
+        let conditional = OwnersConditional::And(vec![
+            OwnersConditional::Or(vec![
+                OwnersConditional::Owner("owner_a".into()),
+                OwnersConditional::Owner("@org/platform".into()),
+            ]),
+            OwnersConditional::Or(vec![
+                OwnersConditional::Owner("owner_b".into()),
+                OwnersConditional::Owner("@org/platform".into()),
+            ]),
+        ]);
+        let weights: BTreeMap<Owner, u32> = [
+            ("@org/platform".into(), 5),
+            ("owner_a".into(), 1),
+            ("owner_b".into(), 2),
+        ]
+        .into();
+
+        for mode in [SolveMode::Exact, SolveMode::Heuristic] {
+            assert_eq!(
+                conditional.cheapest_owner_set(mode, |owner| weights[owner]),
+                Some(WeightedOwnerSet {
+                    owners: [("owner_a".into(), 1), ("owner_b".into(), 2)].into(),
+                    cost: 3,
+                    exact: mode == SolveMode::Exact,
+                })
+            );
+        }
+        assert_eq!(
+            conditional.minimum_owner_sets(SolveMode::Exact).sets,
+            vec![owner_set(&["@org/platform"])]
+        );
+
+        // With uniform costs, fewer owners wins
+        assert_eq!(
+            conditional
+                .cheapest_owner_set(SolveMode::Exact, |_| 1)
+                .map(|set| set.owners.into_keys().collect::<Vec<_>>()),
+            Some(vec![Owner::from("@org/platform")])
+        );
+
+        // Free owners (e.g. bots) are still picked when they are required
+        assert_eq!(
+            OwnersConditional::And(vec![
+                OwnersConditional::Owner("bot".into()),
+                OwnersConditional::Owner("owner_a".into()),
+            ])
+            .cheapest_owner_set(SolveMode::Exact, |owner| if *owner == Owner::from("bot") {
+                0
+            } else {
+                1
+            })
+            .map(|set| set.cost),
+            Some(1)
+        );
+        assert_eq!(
+            OwnersConditional::Or(vec![]).cheapest_owner_set(SolveMode::Auto, |_| 1),
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate() -> anyhow::Result<()> {
+        let conditional = parse("@owner_a && (@owner_b || @org/team) && (@owner_c || @owner_d)");
+
+        assert_eq!(
+            conditional.evaluate(&Approvals::default()),
+            Evaluation::Outstanding(conditional.clone().reduce())
+        );
+
+        let approvals = Approvals {
+            users: ["@owner_a".into()].into(),
+            teams: ["@org/team".into()].into(),
+            ..Approvals::default()
+        };
+        assert_eq!(
+            conditional.evaluate(&approvals),
+            Evaluation::Outstanding(parse("@owner_c || @owner_d"))
+        );
+
+        let approvals = Approvals::from_logins(["owner_a", "owner_b", "owner_d"]);
+        assert_eq!(conditional.evaluate(&approvals), Evaluation::Satisfied);
+        assert_eq!(
+            parse("()").evaluate(&Approvals::default()),
+            Evaluation::Satisfied
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_equivalence_and_diff() -> anyhow::Result<()> {
+        let old = parse("@alice && (@bob || @org/team)");
+
+        assert!(old.is_equivalent(&parse("(@org/team || @bob) && @alice && @alice")));
+        assert!(parse("(@a || @b) && (@a || @c)").is_equivalent(&parse("@a || (@b && @c)")));
+        assert!(
+            parse("2 of (@a || @b || @c)")
+                .is_equivalent(&parse("(@a && @b) || (@a && @c) || (@b && @c)"))
+        );
+        assert!(!old.is_equivalent(&parse("@alice")));
+        // Two people from the team aren't the same as just one
+        assert!(!parse("2 of @org/team").is_equivalent(&parse("@org/team")));
+
+        assert!(old.diff(&parse("(@bob || @org/team) && @alice")).is_empty());
+        assert_eq!(
+            old.diff(&parse("(@bob || @org/team) && @org/infra")),
+            ConditionalDiff {
+                added: vec![parse("@org/infra")],
+                removed: vec![parse("@alice")],
+            }
+        );
+        assert_eq!(
+            old.diff(&parse("@alice && @bob")),
+            ConditionalDiff {
+                added: vec![parse("@bob")],
+                removed: vec![parse("@bob || @org/team")],
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_alternative_owner_sets() -> anyhow::Result<()> {
+        // (a || b) && (b || c) && (c || d)
+        let conditional = OwnersConditional::And(vec![
+            OwnersConditional::Or(vec![
+                OwnersConditional::Owner("owner_a".into()),
+                OwnersConditional::Owner("owner_b".into()),
+            ]),
+            OwnersConditional::Or(vec![
+                OwnersConditional::Owner("owner_b".into()),
+                OwnersConditional::Owner("owner_c".into()),
+            ]),
+            OwnersConditional::Or(vec![
+                OwnersConditional::Owner("owner_c".into()),
+                OwnersConditional::Owner("owner_d".into()),
+            ]),
+        ]);
+        let sets = |alternatives: Vec<WeightedOwnerSet>| {
+            alternatives
+                .into_iter()
+                .map(|set| {
+                    set.owners
+                        .into_keys()
+                        .map(|owner| owner.to_string())
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Sets with redundant owners, like {a, b, d}, are left out
+        assert_eq!(
+            sets(conditional.alternative_owner_sets(10, |_| 0)),
+            vec![
+                vec!["@owner_a", "@owner_c"],
+                vec!["@owner_b", "@owner_c"],
+                vec!["@owner_b", "@owner_d"],
+            ]
+        );
+
+        // Larger sets come after all the smaller ones
+        let conditional = OwnersConditional::And(vec![
+            OwnersConditional::Or(vec![
+                OwnersConditional::Owner("owner_a".into()),
+                OwnersConditional::Owner("owner_b".into()),
+            ]),
+            OwnersConditional::Or(vec![
+                OwnersConditional::Owner("owner_a".into()),
+                OwnersConditional::Owner("owner_c".into()),
+            ]),
+        ]);
+        assert_eq!(
+            sets(conditional.alternative_owner_sets(10, |_| 0)),
+            vec![vec!["@owner_a"], vec!["@owner_b", "@owner_c"]]
+        );
+        let conditional = OwnersConditional::And(vec![
+            OwnersConditional::Or(vec![
+                OwnersConditional::Owner("owner_a".into()),
+                OwnersConditional::Owner("owner_b".into()),
+            ]),
+            OwnersConditional::Or(vec![
+                OwnersConditional::Owner("owner_b".into()),
+                OwnersConditional::Owner("owner_c".into()),
+            ]),
+            OwnersConditional::Or(vec![
+                OwnersConditional::Owner("owner_c".into()),
+                OwnersConditional::Owner("owner_d".into()),
+            ]),
+        ]);
+
+        // Weights only reorder sets of the same size
+        let weights: BTreeMap<Owner, u32> = [
+            ("owner_a".into(), 1),
+            ("owner_b".into(), 5),
+            ("owner_c".into(), 5),
+            ("owner_d".into(), 1),
+        ]
+        .into();
+        let weighted = conditional.alternative_owner_sets(2, |owner| weights[owner]);
+        assert_eq!(
+            sets(weighted.clone()),
+            vec![vec!["@owner_a", "@owner_c"], vec!["@owner_b", "@owner_d"]]
+        );
+        assert_eq!(
+            weighted.iter().map(|set| set.cost).collect::<Vec<_>>(),
+            vec![6, 6]
+        );
+
+        assert_eq!(
+            OwnersConditional::Or(vec![]).alternative_owner_sets(3, |_| 0),
+            vec![]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_teams() -> anyhow::Result<()> {
+        let conditional = parse("(@alice || @org/backend) && @org/infra && @org/web && @org/docs");
+        let members: TeamMembers = [
+            ("@org/backend", vec!["@bob", "@carol"]),
+            ("@org/infra", vec!["@bob", "@dave"]),
+            ("@org/web", vec!["@BOB"]),
+            ("@org/docs", vec![]),
+        ]
+        .into_iter()
+        .map(|(team, members)| (team.into(), members.into_iter().map(Owner::from).collect()))
+        .collect();
+
+        assert_eq!(
+            conditional.teams(),
+            owner_set(&["@org/backend", "@org/docs", "@org/infra", "@org/web"])
+        );
+        let expanded = conditional.clone().expand_teams(&members).reduce();
+        assert_eq!(
+            expanded,
+            parse("@bob && @org/docs && (@alice || @bob || @carol) && (@bob || @dave)").reduce()
+        );
+        assert_eq!(
+            expanded.minimum_owner_sets(SolveMode::Exact).sets,
+            vec![owner_set(&["@bob", "@org/docs"])]
+        );
+
+        // An approval from a member counts for their teams too
+        let approvals = Approvals::from_logins(["bob"]).with_team_members(&members);
+        assert_eq!(
+            conditional.evaluate(&approvals),
+            Evaluation::Outstanding(parse("@org/docs"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_at_least() -> anyhow::Result<()> {
+        let codeowners = CodeownersRules::parse("src/ @a\nsecurity/ @org/security @c\n")
+            .with_approval_counts(vec![
+                "security/**=3".parse().map_err(anyhow::Error::msg)?,
+                "security/docs/*=1".parse().map_err(anyhow::Error::msg)?,
+            ]);
+        assert_eq!(
+            OwnersConditional::from_codeowners(
+                &codeowners,
+                &["src/lib.rs", "security/auth.rs", "security/docs/README.md"]
+            )
+            .reduce(),
+            parse("@a && (@c || @org/security) && 3 of (@c || @org/security)")
+        );
+        assert_eq!(
+            format!("{}", parse("2 of @org/security")),
+            "2 of @org/security"
+        );
+
+        // The team can provide any number of approvals, but @b and @c are
+        // one each
+        let conditional = parse("@a && 3 of (@b || @c || @org/security)").reduce();
+        assert_eq!(
+            conditional.minimum_owner_sets(SolveMode::Exact).sets,
+            vec![owner_set(&["@a", "@org/security"])]
+        );
+        let conditional = parse("2 of (@a || @b || @c) && (@c || @d)").reduce();
+        assert_eq!(
+            conditional.minimum_owner_sets(SolveMode::Exact).sets,
+            vec![owner_set(&["@a", "@c"]), owner_set(&["@b", "@c"])]
+        );
+
+        // Thresholds of one are an `Or`, and needing every owner an `And`
+        assert_eq!(parse("1 of (@a || @b)").reduce(), parse("@a || @b"));
+        assert_eq!(parse("2 of (@b || @a)").reduce(), parse("@a && @b"));
+        assert_eq!(parse("0 of (@a || @b)").reduce(), parse("()"));
+        assert_eq!(parse("2 of @org/t").reduce(), parse("2 of @org/t"));
+
+        // Removing owners never asks for more approvals than are left
+        assert_eq!(
+            parse("3 of (@a || @b || @c)")
+                .remove_all(&["@c".into()].into())
+                .map(OwnersConditional::reduce),
+            Some(parse("@a && @b"))
+        );
+
+        // Every approving member of a team counts towards the threshold
+        let members: TeamMembers = [(Owner::from("@org/security"), owner_set(&["@x", "@y", "@z"]))]
+            .into_iter()
+            .collect();
+        let conditional = parse("2 of @org/security");
+        assert_eq!(
+            conditional.evaluate(&Approvals::from_logins(["x"]).with_team_members(&members)),
+            Evaluation::Outstanding(parse("@org/security"))
+        );
+        assert_eq!(
+            conditional.evaluate(&Approvals::from_logins(["x", "z"]).with_team_members(&members)),
+            Evaluation::Satisfied
+        );
+        assert_eq!(
+            conditional.clone().expand_teams(&members).reduce(),
+            parse("2 of (@x || @y || @z)")
+        );
+
+        // Past the cap on clauses, the solvers work from a stricter
+        // requirement and say they're approximate
+        assert_eq!(binomial_at_most(16, 8, 12_870), Some(12_870));
+        assert_eq!(binomial_at_most(16, 8, 12_869), None);
+        assert_eq!(binomial_at_most(3, 4, 0), Some(0));
+        let threshold = |count: usize, owners: usize| {
+            OwnersConditional::AtLeast(
+                count,
+                (0..owners)
+                    .map(|i| OwnersConditional::Owner(Owner::User(format!("o{i:02}"))))
+                    .collect(),
+            )
+        };
+        let at_cap = threshold(8, 15);
+        assert!(!at_cap.has_capped_threshold());
+        assert_eq!(at_cap.clauses().len(), 6_435);
+        for capped in [threshold(9, 16), threshold(10, 30)] {
+            assert!(capped.has_capped_threshold());
+            assert!(capped.is_satisfiable());
+            assert!(capped.is_equivalent(&capped));
+            let minimum = capped.minimum_owner_sets(SolveMode::Exact);
+            assert!(!minimum.exact);
+            let approvals = Approvals::from_logins(
+                minimum.sets[0]
+                    .iter()
+                    .map(|owner| owner.to_string().split_off(1)),
+            );
+            assert_eq!(capped.evaluate(&approvals), Evaluation::Satisfied);
+        }
+
+        Ok(())
+    }
+
+    fn parse(text: &str) -> OwnersConditional {
+        text.parse().expect("test conditional parses")
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        assert_eq!(
+            parse("(@a && (@b || @org/team-c))"),
+            OwnersConditional::And(vec![
+                OwnersConditional::Owner("@a".into()),
+                OwnersConditional::Or(vec![
+                    OwnersConditional::Owner("@b".into()),
+                    OwnersConditional::Owner("@org/team-c".into()),
+                ]),
+            ])
+        );
+        assert_eq!(parse("  @a  "), OwnersConditional::Owner("@a".into()));
+        assert_eq!(
+            parse("@a || me@example.com"),
+            parse("(@a || me@example.com)")
+        );
+        assert_eq!(parse("()"), OwnersConditional::And(vec![]));
+        assert_eq!(parse("( || )"), OwnersConditional::Or(vec![]));
+        assert!(!parse("(||)").is_satisfiable());
+        // What's left after removing the only owner stays unsatisfiable when
+        // read back out of a comment
+        let removed = parse("(@a && @b)").remove_owner(&"@a".into());
+        assert_eq!(format!("{removed}"), "((||) && @b)");
+        assert!(!parse(&format!("{removed}")).is_satisfiable());
+        assert_eq!(
+            parse(r#""odd (name)" && "quote\"d""#),
+            OwnersConditional::And(vec![
+                OwnersConditional::Owner("odd (name)".into()),
+                OwnersConditional::Owner("quote\"d".into()),
+            ])
+        );
+
+        // Anything reduced round trips through `Display`
+        for text in [
+            "@a",
+            "()",
+            "(||)",
+            "(@a && @b)",
+            "(@a && @org/team && (@b || @c) && (@d || (@e && @f)))",
+            "(@a && 2 of @org/team && 2 of (@b || @c || (@d && @e)))",
+            r#"("@odd (name)" && ("@with \"quotes\" and \\" || @x))"#,
+        ] {
+            let conditional = parse(text).reduce();
+            assert_eq!(format!("{conditional}"), text);
+            assert_eq!(parse(&format!("{conditional}")), conditional);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() -> anyhow::Result<()> {
+        let error = |text: &str| text.parse::<OwnersConditional>().unwrap_err();
+
+        assert_eq!(
+            error("(@a && @b"),
+            ParseError {
+                offset: 0,
+                kind: ParseErrorKind::UnclosedParen
+            }
+        );
+        assert_eq!(
+            error("@a && @b || @c"),
+            ParseError {
+                offset: 9,
+                kind: ParseErrorKind::MixedOperators
+            }
+        );
+        assert_eq!(
+            error("@a & @b"),
+            ParseError {
+                offset: 3,
+                kind: ParseErrorKind::Unexpected('&')
+            }
+        );
+        assert_eq!(
+            error("@a &&"),
+            ParseError {
+                offset: 5,
+                kind: ParseErrorKind::UnexpectedEnd
+            }
+        );
+        assert_eq!(
+            error("(@a) @b"),
+            ParseError {
+                offset: 5,
+                kind: ParseErrorKind::Unexpected('@')
+            }
+        );
+        assert_eq!(
+            error(r#"@a || "@b"#),
+            ParseError {
+                offset: 6,
+                kind: ParseErrorKind::UnterminatedQuote
+            }
+        );
+        assert_eq!(
+            format!("{}", error("(@a || )")),
+            "expected an owner or '(' at offset 7"
+        );
+
         Ok(())
     }
 }
@@ -1,5 +1,10 @@
+use crate::{owner::Owner, render::RenderFormat, rules::ApprovalCount};
 use serde::Deserialize;
-use std::{collections::HashSet, path::PathBuf, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    time::Duration,
+};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
@@ -18,7 +23,86 @@ pub struct Config {
     #[serde(default)]
     pub datadog_socket: Option<PathBuf>,
     #[serde(default)]
-    pub exclude_owners: HashSet<String>,
+    pub exclude_owners: HashSet<Owner>,
+    // How many alternative sets of reviewers to list in the comment
+    #[serde(default = "default_alternatives")]
+    pub alternatives: usize,
+    // If set, we also suggest the cheapest set of reviewers using these costs
+    #[serde(default)]
+    pub weights: Option<WeightsConfig>,
+    // If set, teams are expanded into their members before looking for the
+    // smallest set of reviewers
+    #[serde(default)]
+    pub team_expansion: Option<TeamExpansionConfig>,
+    // Paths that need approvals from more than one of their owners
+    #[serde(default)]
+    pub approval_counts: Vec<ApprovalCount>,
+    // What to do about changed files that have no owners
+    #[serde(default)]
+    pub unowned_files: UnownedFiles,
+    // How to show the requirement in the comment, on top of the expression
+    #[serde(default)]
+    pub render_format: RenderFormat,
+    // Path of the CODEOWNERS file in the repo. If unset we look wherever
+    // GitHub does: `.github/`, the root of the repo, then `docs/`
+    #[serde(default)]
+    pub codeowners_path: Option<String>,
+}
+
+/// What to do about changed files that no CODEOWNERS rule gives an owner to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnownedFiles {
+    /// List them in the comment, without requiring anyone's review.
+    #[default]
+    Allow,
+    /// Require a review from any of these owners.
+    Fallback(Vec<Owner>),
+    /// Report the PR as blocked until they have owners.
+    Block,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WeightsConfig {
+    // Cost of any individual user not listed in `owners`
+    #[serde(default = "default_weight")]
+    pub default_user: u32,
+    // Cost of any team not listed in `owners`
+    #[serde(default = "default_weight")]
+    pub default_team: u32,
+    // Cost of specific users or teams, keyed the same way they appear in the
+    // CODEOWNERS file (e.g. `@user` or `@org/team`)
+    #[serde(default)]
+    pub owners: HashMap<Owner, u32>,
+}
+
+impl WeightsConfig {
+    pub fn weight_of(&self, owner: &Owner) -> u32 {
+        match self.owners.get(owner) {
+            Some(weight) => *weight,
+            None if owner.is_team() => self.default_team,
+            None => self.default_user,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TeamExpansionConfig {
+    // How long to cache team memberships for before fetching them again
+    #[serde(default = "default_team_members_ttl")]
+    pub ttl: Duration,
+}
+
+fn default_alternatives() -> usize {
+    3
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn default_team_members_ttl() -> Duration {
+    Duration::from_secs(60 * 60)
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -0,0 +1,244 @@
+//! How much of a repository CODEOWNERS covers, and by whom.
+//!
+//! The JSON looks like this:
+//!
+//! ```json
+//! {
+//!   "total_files": 4,
+//!   "unowned_files": ["README.md"],
+//!   "files_per_owner": {"@a": 2, "@org/b": 1},
+//!   "mixed_directories": [
+//!     {"path": "src", "owner_sets": [{"owners": ["@a"], "files": 2}, {"owners": ["@org/b"], "files": 1}]}
+//!   ]
+//! }
+//! ```
+//!
+//! - `unowned_files` lists the files no rule gives an owner, either because no
+//!   rule matches them or because the last match lists no owners.
+//! - `files_per_owner` counts the files each owner is listed for.
+//! - `mixed_directories` lists the directories whose files, not counting those
+//!   in subdirectories, don't all have the same owners. An empty `owners` list
+//!   stands for the unowned files. The root of the repo is `""`.
+use crate::{conditional, owner::Owner, rules::CodeownersRules};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CoverageReport {
+    pub total_files: usize,
+    pub unowned_files: Vec<String>,
+    pub files_per_owner: BTreeMap<Owner, usize>,
+    pub mixed_directories: Vec<MixedDirectory>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MixedDirectory {
+    pub path: String,
+    pub owner_sets: Vec<OwnerSetFiles>,
+}
+
+/// How many files in a directory have exactly these owners.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OwnerSetFiles {
+    pub owners: Vec<Owner>,
+    pub files: usize,
+}
+
+impl CoverageReport {
+    pub fn new(codeowners: &CodeownersRules, files: &[&str]) -> CoverageReport {
+        let mut unowned_files = vec![];
+        let mut files_per_owner = BTreeMap::new();
+        let mut directories: BTreeMap<&str, BTreeMap<Vec<Owner>, usize>> = BTreeMap::new();
+        for (file, owners) in conditional::to_owners_map(codeowners, files) {
+            let owners = owners.unwrap_or_default();
+            if owners.is_empty() {
+                unowned_files.push(file.to_string());
+            }
+            for owner in &owners {
+                *files_per_owner.entry(owner.clone()).or_default() += 1;
+            }
+            let directory = file.rsplit_once('/').map_or("", |(directory, _)| directory);
+            *directories
+                .entry(directory)
+                .or_default()
+                .entry(owners)
+                .or_default() += 1;
+        }
+
+        let mixed_directories = directories
+            .into_iter()
+            .filter(|(_, owner_sets)| owner_sets.len() > 1)
+            .map(|(path, owner_sets)| MixedDirectory {
+                path: path.to_string(),
+                owner_sets: owner_sets
+                    .into_iter()
+                    .map(|(owners, files)| OwnerSetFiles { owners, files })
+                    .collect(),
+            })
+            .collect();
+        CoverageReport {
+            total_files: files.len(),
+            unowned_files,
+            files_per_owner,
+            mixed_directories,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Renders the report as CSV with the columns `kind,path,owners,files`.
+    /// There is a row of kind `unowned` for each unowned file, `owner` for
+    /// each owner and `mixed_directory` for each set of owners within a mixed
+    /// directory. Owners are separated by spaces.
+    pub fn to_csv(&self) -> String {
+        let mut rows = vec!["kind,path,owners,files".to_string()];
+        for file in &self.unowned_files {
+            rows.push(format!("unowned,{},,1", csv_field(file)));
+        }
+        for (owner, files) in &self.files_per_owner {
+            rows.push(format!("owner,,{},{files}", csv_field(&owner.to_string())));
+        }
+        for directory in &self.mixed_directories {
+            for owner_set in &directory.owner_sets {
+                rows.push(format!(
+                    "mixed_directory,{},{},{}",
+                    csv_field(&directory.path),
+                    csv_field(&join_owners(&owner_set.owners)),
+                    owner_set.files
+                ));
+            }
+        }
+        rows.join("\n") + "\n"
+    }
+
+    pub fn to_text(&self) -> String {
+        let owned = self.total_files - self.unowned_files.len();
+        let percent = if self.total_files == 0 {
+            100.0
+        } else {
+            owned as f64 * 100.0 / self.total_files as f64
+        };
+        let mut text = format!(
+            "{owned} of {} files have owners ({percent:.1}%)\n",
+            self.total_files
+        );
+
+        if !self.unowned_files.is_empty() {
+            text.push_str("\nUnowned files:\n");
+            for file in &self.unowned_files {
+                text.push_str(&format!("  {file}\n"));
+            }
+        }
+        if !self.files_per_owner.is_empty() {
+            text.push_str("\nFiles per owner:\n");
+            for (owner, files) in &self.files_per_owner {
+                text.push_str(&format!("  {owner}: {files}\n"));
+            }
+        }
+        if !self.mixed_directories.is_empty() {
+            text.push_str("\nDirectories with mixed ownership:\n");
+            for directory in &self.mixed_directories {
+                let owner_sets = directory
+                    .owner_sets
+                    .iter()
+                    .map(|owner_set| {
+                        let owners = if owner_set.owners.is_empty() {
+                            "unowned".to_string()
+                        } else {
+                            join_owners(&owner_set.owners)
+                        };
+                        format!("{owners}: {}", owner_set.files)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                text.push_str(&format!("  {}/ ({owner_sets})\n", directory.path));
+            }
+        }
+        text
+    }
+}
+
+fn join_owners(owners: &[Owner]) -> String {
+    owners
+        .iter()
+        .map(|owner| owner.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quotes a CSV field if it needs it.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CoverageReport;
+    use crate::rules::CodeownersRules;
+
+    #[test]
+    fn test_coverage() -> anyhow::Result<()> {
+        let codeowners =
+            CodeownersRules::parse("/src/ @a\n/src/b.rs @org/b\n/src/gen/\n/docs/ @a @org/b\n");
+        let report = CoverageReport::new(
+            &codeowners,
+            &[
+                "README.md",
+                "src/a.rs",
+                "src/b.rs",
+                "src/c.rs",
+                "src/gen/d.rs",
+                "docs/a,b.md",
+            ],
+        );
+
+        assert_eq!(
+            report.to_text(),
+            r#"4 of 6 files have owners (66.7%)
+
+Unowned files:
+  README.md
+  src/gen/d.rs
+
+Files per owner:
+  @a: 3
+  @org/b: 2
+
+Directories with mixed ownership:
+  src/ (@a: 2, @org/b: 1)
+"#
+        );
+        assert_eq!(
+            report.to_csv(),
+            r#"kind,path,owners,files
+unowned,README.md,,1
+unowned,src/gen/d.rs,,1
+owner,,@a,3
+owner,,@org/b,2
+mixed_directory,src,@a,2
+mixed_directory,src,@org/b,1
+"#
+        );
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()?)?;
+        assert_eq!(json["files_per_owner"]["@org/b"], 2);
+        assert_eq!(
+            json["mixed_directories"][0]["owner_sets"][1]["owners"][0],
+            "@org/b"
+        );
+
+        let root = CoverageReport::new(&codeowners, &["README.md", "LICENSE, v2"]);
+        assert_eq!(
+            root.to_csv(),
+            "kind,path,owners,files\nunowned,\"LICENSE, v2\",,1\nunowned,README.md,,1\n"
+        );
+        assert!(root.mixed_directories.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,13 +1,18 @@
+use anyhow::Context;
 use clap::Parser;
-use codeowners::Owners;
 use futures_util::future::join_all;
 use jsonwebtoken::EncodingKey;
 use min_review_bot::{
     cache::Cache,
-    conditional::OwnersConditional,
-    config::Config,
+    conditional::{Approvals, OwnersConditional, SolveMode},
+    config::{Config, UnownedFiles},
     github::{GithubSource, Repo, RepoConnector, RepoSource},
     metrics::MetricsReporter,
+    owner::{Owner, TeamMembers},
+    ownership_diff::OwnershipDiff,
+    provenance::TrackedConditional,
+    report::OwnershipReport,
+    rules::CodeownersRules,
 };
 use octocrab::{
     Octocrab,
@@ -18,6 +23,7 @@ use opentelemetry_api::KeyValue;
 use std::{
     collections::{BTreeMap, BTreeSet, HashSet},
     path::PathBuf,
+    sync::Arc,
     time::{Duration, SystemTime},
 };
 use tokio::time::Instant;
@@ -80,11 +86,22 @@ async fn inner_update_loop(
     repo_connector: &RepoConnector<GithubSource>,
     config: &Config,
 ) -> anyhow::Result<()> {
-    let (prs, codeowners, updates) = fetch_pr_info(db, repo_connector, config).await?;
+    let (prs, updates, checked_codeowners) = fetch_pr_info(db, repo_connector, config).await?;
+    let codeowners = load_codeowners(&prs, db, repo_connector, config).await;
 
-    let process_iter = prs
-        .into_iter()
-        .map(|pr| process_pr(pr, &updates, &codeowners, config, db, repo_connector));
+    // Like GitHub, we use the CODEOWNERS file on the branch each PR targets
+    let process_iter = prs.into_iter().filter_map(|pr| {
+        let codeowners = codeowners.get(&pr.base.ref_field)?;
+        Some(process_pr(
+            pr,
+            &updates,
+            &checked_codeowners,
+            codeowners,
+            config,
+            db,
+            repo_connector,
+        ))
+    });
 
     join_all(process_iter)
         .await
@@ -94,44 +111,218 @@ async fn inner_update_loop(
     Ok(())
 }
 
+/// The CODEOWNERS file a branch uses.
+struct BranchCodeowners {
+    rules: Arc<CodeownersRules>,
+    sha: String,
+}
+
+/// Loads the CODEOWNERS file of every branch targeted by one of `prs`, keyed
+/// by branch name. Branches we can't load one for are left out.
+#[instrument(level = "info", skip_all)]
+async fn load_codeowners<S: RepoSource>(
+    prs: &[PullRequest],
+    db: &Cache,
+    repo_connector: &RepoConnector<S>,
+    config: &Config,
+) -> BTreeMap<String, BranchCodeowners> {
+    let base_refs: BTreeSet<&str> = prs.iter().map(|pr| pr.base.ref_field.as_str()).collect();
+    let mut by_ref = BTreeMap::new();
+    for base_ref in base_refs {
+        match load_branch_codeowners(base_ref, db, repo_connector, config).await {
+            Ok(codeowners) => {
+                by_ref.insert(base_ref.to_string(), codeowners);
+            }
+            Err(e) => {
+                error!(error = ?e, base_ref, "could not load CODEOWNERS; skipping prs against this branch");
+            }
+        }
+    }
+    // Forget versions that no open PR targets anymore
+    let in_use = by_ref
+        .values()
+        .map(|codeowners| codeowners.sha.clone())
+        .collect();
+    db.retain_parsed_codeowners(&in_use);
+    by_ref
+}
+
+/// Loads the CODEOWNERS file `base_ref` uses. It's only downloaded if it
+/// changed since the last loop, and only parsed once per version.
+async fn load_branch_codeowners<S: RepoSource>(
+    base_ref: &str,
+    db: &Cache,
+    repo_connector: &RepoConnector<S>,
+    config: &Config,
+) -> anyhow::Result<BranchCodeowners> {
+    let cached = db.get_codeowners_ref(base_ref).await?;
+    let (location, fetched) = repo_connector
+        .get_codeowners_file_if_modified(
+            config.codeowners_path.as_deref(),
+            Some(base_ref),
+            cached.as_ref().map(|(location, _)| location),
+        )
+        .await?;
+    let (sha, content, changed) = match (fetched, cached) {
+        (Some(file), cached) => {
+            db.update_codeowners_ref(base_ref, &location, &file.sha, &file.content)
+                .await?;
+            let changed = cached.is_none_or(|(_, sha)| sha != file.sha);
+            (file.sha, Some(file.content), changed)
+        }
+        (None, Some((cached_location, sha))) => {
+            if location != cached_location {
+                db.update_codeowners_location(base_ref, &location).await?;
+            }
+            (sha, None, false)
+        }
+        // GitHub only says a file is unmodified if we sent its ETag, which we
+        // only have if it's cached
+        (None, None) => {
+            anyhow::bail!("GitHub says CODEOWNERS on {base_ref} is unmodified, but it isn't cached")
+        }
+    };
+    if changed {
+        info!(base_ref, sha, "CODEOWNERS changed");
+    }
+
+    let rules = match db.get_parsed_codeowners(&sha) {
+        Some(rules) => rules,
+        None => {
+            let content = match content {
+                Some(content) => content,
+                None => db
+                    .get_codeowners_content(&sha)
+                    .await?
+                    .context("CODEOWNERS content missing from the cache")?,
+            };
+            let codeowners = CodeownersRules::parse(&content)
+                .with_approval_counts(config.approval_counts.clone());
+            for diagnostic in codeowners.diagnostics() {
+                warn!(base_ref, "skipping CODEOWNERS line: {diagnostic}");
+            }
+            db.insert_parsed_codeowners(&sha, codeowners)
+        }
+    };
+    Ok(BranchCodeowners { rules, sha })
+}
+
+/// Everything we work out about a single PR's ownership requirements.
+struct PrOwnership {
+    conditional: OwnersConditional,
+    tracked: TrackedConditional,
+    changed_files: BTreeSet<String>,
+    approvals: Approvals,
+    /// Only set if team expansion is enabled.
+    team_members: Option<TeamMembers>,
+    /// Only set if the PR edits CODEOWNERS.
+    ownership_diff: Option<OwnershipDiff>,
+}
+
 #[instrument(level = "info", skip_all, fields(pr_num = pr.number), err)]
 async fn process_pr(
     pr: PullRequest,
     updates: &BTreeMap<u64, SystemTime>,
-    codeowners: &Owners,
+    checked_codeowners: &BTreeMap<u64, String>,
+    codeowners: &BranchCodeowners,
     config: &Config,
     db: &Cache,
     repo_connector: &RepoConnector<GithubSource>,
 ) -> anyhow::Result<()> {
-    if should_update_pr(&pr, updates, config) {
-        let (conditional, changed_files) = get_pr_conditional(
+    if should_update_pr(&pr, updates, checked_codeowners, config, &codeowners.sha) {
+        let codeowners_sha = &codeowners.sha;
+        let codeowners = &codeowners.rules;
+        let author = pr.user.as_ref().map(|user| Owner::User(user.login.clone()));
+        let (tracked, changed_files) = get_pr_conditional(
             pr.number,
             repo_connector,
             codeowners,
             &config.exclude_owners,
+            &config.unowned_files,
+            author.as_ref(),
         )
         .await?;
-        update_pr(
-            config,
-            &pr,
-            repo_connector,
-            db,
-            codeowners,
+        MetricsReporter::report_unowned_files(tracked.unowned_files.len());
+        let conditional = tracked.conditional();
+        let team_members = match &config.team_expansion {
+            Some(team_expansion) => Some(
+                get_team_members(db, repo_connector, &conditional.teams(), team_expansion.ttl)
+                    .await?,
+            ),
+            None => None,
+        };
+        let mut approvals =
+            Approvals::from_logins(repo_connector.get_pr_approvers(pr.number).await?);
+        if let Some(team_members) = &team_members {
+            approvals = approvals.with_team_members(team_members);
+        }
+        // Not being able to explain a CODEOWNERS edit shouldn't stop the rest
+        // of the comment from being updated
+        let ownership_diff =
+            match get_ownership_diff(&pr, &changed_files, config, db, repo_connector).await {
+                Ok(ownership_diff) => ownership_diff,
+                Err(e) => {
+                    warn!(error = ?e, "could not work out how the pr changes CODEOWNERS");
+                    None
+                }
+            };
+        let ownership = PrOwnership {
             conditional,
+            tracked,
             changed_files,
-        )
-        .await?;
+            approvals,
+            team_members,
+            ownership_diff,
+        };
+        update_pr(config, &pr, repo_connector, db, codeowners, ownership).await?;
+        db.update_checked_codeowners(pr.id.0, codeowners_sha)
+            .await?;
     }
     Ok(())
 }
 
+/// Works out how the PR changes file ownership, if it edits CODEOWNERS. That
+/// means listing every file in the repo, so the result is kept until either
+/// side of the CODEOWNERS edit changes.
+async fn get_ownership_diff(
+    pr: &PullRequest,
+    changed_files: &BTreeSet<String>,
+    config: &Config,
+    db: &Cache,
+    repo_connector: &RepoConnector<GithubSource>,
+) -> anyhow::Result<Option<OwnershipDiff>> {
+    let change = match repo_connector
+        .get_codeowners_change(
+            config.codeowners_path.as_deref(),
+            changed_files,
+            &pr.base.ref_field,
+            &pr.head.sha,
+        )
+        .await?
+    {
+        Some(change) => change,
+        None => return Ok(None),
+    };
+    if let Some(diff) = db.get_ownership_diff(pr.id.0, &change).await? {
+        return Ok(Some(diff));
+    }
+    let diff = repo_connector.get_ownership_diff(&change).await?;
+    db.update_ownership_diff(pr.id.0, &change, &diff).await?;
+    Ok(Some(diff))
+}
+
 #[instrument(level = "info", skip_all, err)]
 async fn fetch_pr_info(
     db: &Cache,
     repo_connector: &RepoConnector<GithubSource>,
     config: &Config,
-) -> anyhow::Result<(Vec<PullRequest>, Owners, BTreeMap<u64, SystemTime>)> {
+) -> anyhow::Result<(
+    Vec<PullRequest>,
+    BTreeMap<u64, SystemTime>,
+    BTreeMap<u64, String>,
+)> {
     let updates = db.get_all_last_updates().await?;
+    let checked_codeowners = db.get_all_checked_codeowners().await?;
     let prs: Vec<_> = repo_connector
         .get_open_prs()
         .await?
@@ -144,21 +335,25 @@ async fn fetch_pr_info(
             }
         })
         .collect();
-    let codeowners_data = repo_connector.get_codeowners_content().await?;
-    let codeowners = codeowners::from_reader(codeowners_data.as_bytes());
 
-    Ok((prs, codeowners, updates))
+    Ok((prs, updates, checked_codeowners))
 }
 
 #[instrument(level = "info", skip_all, fields(pr_num = pr.number), ret)]
 fn should_update_pr(
     pr: &PullRequest,
     updates: &BTreeMap<u64, SystemTime>,
+    checked_codeowners: &BTreeMap<u64, String>,
     config: &Config,
+    codeowners_sha: &str,
 ) -> bool {
     if config.banned_prs.contains(&pr.number) {
         info!("pr is banned");
         false
+    } else if checked_codeowners.get(&pr.id.0).map(String::as_str) != Some(codeowners_sha) {
+        // The requirement can change even if the PR didn't
+        info!("CODEOWNERS on the base branch changed since the pr was last checked");
+        true
     } else {
         updates
             .get(&pr.number)
@@ -177,19 +372,67 @@ fn should_update_pr(
 async fn get_pr_conditional<S: RepoSource>(
     pr_id: u64,
     repo_connector: &RepoConnector<S>,
-    codeowners: &Owners,
-    exclude_owners: &HashSet<String>,
-) -> anyhow::Result<(OwnersConditional, BTreeSet<String>)> {
+    codeowners: &CodeownersRules,
+    exclude_owners: &HashSet<Owner>,
+    unowned_files: &UnownedFiles,
+    author: Option<&Owner>,
+) -> anyhow::Result<(TrackedConditional, BTreeSet<String>)> {
     let changed_files = repo_connector.get_pr_changed_files(pr_id).await?;
     info!(changed_files =? changed_files, "changed files");
     let changed_files_slc: Vec<&str> = changed_files.iter().map(|f| f.as_ref()).collect();
-    Ok((
-        OwnersConditional::from_codeowners(codeowners, &changed_files_slc[..])
-            .remove_all(exclude_owners)
-            .unwrap_or(OwnersConditional::And(Vec::new()))
-            .reduce(),
-        changed_files,
-    ))
+    let mut tracked = TrackedConditional::from_codeowners(codeowners, &changed_files_slc[..]);
+    if !tracked.unowned_files.is_empty() {
+        info!(unowned_files = ?tracked.unowned_files, "unowned files");
+    }
+    if let UnownedFiles::Fallback(owners) = unowned_files {
+        tracked = tracked.with_fallback_owners(owners);
+    }
+    let mut tracked = tracked.remove_all(exclude_owners);
+    if let Some(author) = author {
+        tracked = tracked.remove_author(author);
+    }
+    let tracked = tracked.reduce();
+    if !tracked.no_eligible_owner.is_empty() {
+        warn!(
+            no_eligible_owner = tracked.no_eligible_owner.len(),
+            "some requirements can only be satisfied by the pr author"
+        );
+    }
+    Ok((tracked, changed_files))
+}
+
+/// Looks up the members of every team in `teams`, using the cached membership
+/// if it was fetched within `ttl`. Teams we can't look up (e.g. because the app
+/// can't see them) are left unexpanded.
+#[instrument(level = "info", skip_all, err)]
+async fn get_team_members<S: RepoSource>(
+    db: &Cache,
+    repo_connector: &RepoConnector<S>,
+    teams: &BTreeSet<Owner>,
+    ttl: Duration,
+) -> anyhow::Result<TeamMembers> {
+    let mut team_members = TeamMembers::default();
+    for owner in teams {
+        let Owner::Team { org, team } = owner else {
+            continue;
+        };
+        let members = match db.get_team_members(org, team, ttl).await? {
+            Some(members) => members,
+            None => match repo_connector.get_team_members(org, team).await {
+                Ok(members) => {
+                    db.update_team_members(org, team, &members, SystemTime::now())
+                        .await?;
+                    members
+                }
+                Err(e) => {
+                    warn!(error = ?e, team = %owner, "could not fetch team members; not expanding");
+                    continue;
+                }
+            },
+        };
+        team_members.insert(owner.clone(), members);
+    }
+    Ok(team_members)
 }
 
 #[instrument(
@@ -203,25 +446,36 @@ async fn update_pr(
     pr: &PullRequest,
     repo_connector: &RepoConnector<GithubSource>,
     db: &Cache,
-    codeowners: &Owners,
-    conditional: OwnersConditional,
-    changed_files: BTreeSet<String>,
+    codeowners: &CodeownersRules,
+    ownership: PrOwnership,
 ) -> anyhow::Result<()> {
-    let file_owners = min_review_bot::display_file_owners(
+    let previous_body = repo_connector
+        .get_bot_comment(pr.number, &config.bot_username)
+        .await?
+        .and_then(|comment| comment.body);
+    let previous = previous_body.as_deref().and_then(previous_requirement);
+    if let Some(previous) = &previous
+        && !previous.diff(&ownership.conditional).is_empty()
+    {
+        info!(previous = %previous, current = %ownership.conditional, "requirements changed");
+    }
+    let noted: Option<(OwnersConditional, OwnersConditional)> = db
+        .get_requirement_change(pr.id.0)
+        .await?
+        .and_then(|(previous, current)| Some((previous.parse().ok()?, current.parse().ok()?)));
+    let changed_from = requirement_changed_from(previous, noted, &ownership.conditional);
+    let author = pr.user.as_ref().map(|user| Owner::User(user.login.clone()));
+    let comment = render_comment(
+        config,
+        author.as_ref(),
         codeowners,
-        &changed_files.iter().map(|f| f.as_ref()).collect::<Vec<_>>(),
-    );
-    let comment = format!(
-        r#"# File Owners
-The minimum set of reviewers required are:
-`{conditional}`
-<details>
-    <summary>Details</summary>
-    {file_owners}
-</detils>"#
-    );
+        &ownership,
+        changed_from.as_ref(),
+    )?;
 
-    if config.dry_run {
+    if previous_body.as_ref() == Some(&comment) {
+        info!("comment is unchanged; not editing it");
+    } else if config.dry_run {
         info!(
             pr_number = pr.id.0,
             comment = ?comment,
@@ -232,6 +486,16 @@ The minimum set of reviewers required are:
             .add_or_edit_comment(pr.number, comment, config.bot_username.clone())
             .await?;
     }
+    if !config.dry_run
+        && let Some(changed_from) = &changed_from
+    {
+        db.update_requirement_change(
+            pr.id.0,
+            &changed_from.to_string(),
+            &ownership.conditional.to_string(),
+        )
+        .await?;
+    }
 
     let updated_at_systime = pr
         .updated_at
@@ -242,6 +506,137 @@ The minimum set of reviewers required are:
     Ok(())
 }
 
+/// The requirement to note changes from at the top of the comment, if any.
+/// That's the requirement in the bot's last comment if it's different, or
+/// else the one from the last change noted, as long as the requirement hasn't
+/// changed since. Requirements that only differ in order are the same.
+fn requirement_changed_from(
+    previous: Option<OwnersConditional>,
+    noted: Option<(OwnersConditional, OwnersConditional)>,
+    current: &OwnersConditional,
+) -> Option<OwnersConditional> {
+    match previous {
+        Some(previous) if !previous.diff(current).is_empty() => Some(previous),
+        _ => noted
+            .filter(|(_, noted_current)| noted_current.diff(current).is_empty())
+            .map(|(noted_previous, _)| noted_previous),
+    }
+}
+
+/// Writes the bot's comment for a PR, starting with a note of how the
+/// requirement changed since `changed_from` if given.
+fn render_comment(
+    config: &Config,
+    author: Option<&Owner>,
+    codeowners: &CodeownersRules,
+    ownership: &PrOwnership,
+    changed_from: Option<&OwnersConditional>,
+) -> anyhow::Result<String> {
+    let PrOwnership {
+        conditional,
+        tracked,
+        changed_files,
+        approvals,
+        team_members,
+        ownership_diff,
+    } = ownership;
+    let changed_files_slc = changed_files.iter().map(|f| f.as_ref()).collect::<Vec<_>>();
+    let file_owners = min_review_bot::display_file_owners(codeowners, &changed_files_slc);
+    // With team expansion enabled we look for reviewers among the members of
+    // each team, but still show the requirement as written in CODEOWNERS
+    let solved = match team_members {
+        Some(team_members) => conditional.clone().expand_teams(team_members).reduce(),
+        None => conditional.clone(),
+    };
+    let minimum = solved.minimum_owner_sets(SolveMode::Auto);
+    info!(minimum = %minimum, exact = minimum.exact, "minimum owner sets");
+    let blocked_files = min_review_bot::blocked_files(tracked, &config.unowned_files);
+    let report_block = OwnershipReport::new(
+        codeowners,
+        &changed_files_slc,
+        conditional.clone(),
+        minimum.clone(),
+        blocked_files.clone(),
+    )
+    .to_comment_block()?;
+    let minimum_owners = min_review_bot::display_minimum_owner_sets(&minimum);
+    let team_coverage = match team_members {
+        Some(team_members) => {
+            min_review_bot::display_team_coverage(conditional, &minimum, team_members)
+        }
+        None => String::new(),
+    };
+    let cheapest_owners = match &config.weights {
+        Some(weights) => {
+            let cheapest =
+                solved.cheapest_owner_set(SolveMode::Auto, |owner| weights.weight_of(owner));
+            format!(
+                "\nCheapest group of reviewers: {}\n",
+                min_review_bot::display_weighted_owner_set(cheapest.as_ref())
+            )
+        }
+        None => String::new(),
+    };
+    let alternatives = match &config.weights {
+        Some(weights) => {
+            solved.alternative_owner_sets(config.alternatives, |owner| weights.weight_of(owner))
+        }
+        None => solved.alternative_owner_sets(config.alternatives, |_| 0),
+    };
+    let alternatives =
+        min_review_bot::display_alternative_owner_sets(&alternatives, config.weights.is_some());
+    let approval_status =
+        min_review_bot::display_approval_status(conditional, approvals, &blocked_files);
+    let rendered_requirement =
+        min_review_bot::display_rendered_requirement(conditional, config.render_format, approvals);
+    let provenance = min_review_bot::display_provenance(tracked);
+    let optional_reviews = min_review_bot::display_optional_reviews(tracked, approvals);
+    let codeowners_changes = min_review_bot::display_codeowners_changes(ownership_diff.as_ref());
+    let unowned_files = min_review_bot::display_unowned_files(tracked, &config.unowned_files);
+    let no_eligible_owner = match author {
+        Some(author) => min_review_bot::display_no_eligible_owner(tracked, author),
+        None => String::new(),
+    };
+    let requirement_changes = match changed_from {
+        Some(changed_from) => {
+            min_review_bot::display_requirement_changes(&changed_from.diff(conditional))
+        }
+        None => String::new(),
+    };
+    let comment = format!(
+        r#"# File Owners
+{requirement_changes}{no_eligible_owner}The minimum set of reviewers required are:
+`{conditional}`
+{rendered_requirement}
+Smallest group of reviewers that satisfies this: {minimum_owners}
+{team_coverage}{cheapest_owners}{alternatives}
+## Approval status
+{approval_status}
+
+{codeowners_changes}{optional_reviews}{unowned_files}<details>
+    <summary>Why each reviewer is needed</summary>
+
+{provenance}
+</details>
+<details>
+    <summary>Details</summary>
+    {file_owners}
+</detils>
+{report_block}"#
+    );
+
+    Ok(comment)
+}
+
+/// Reads the requirement out of a comment previously left by the bot, from the
+/// hidden report if there is one, or from the comment text otherwise.
+fn previous_requirement(body: &str) -> Option<OwnersConditional> {
+    match OwnershipReport::from_comment(body) {
+        Some(report) => report.ok().map(|report| report.requirement),
+        None => min_review_bot::requirement_from_comment(body)?.ok(),
+    }
+}
+
 fn setup_tracing(config: &Config) -> anyhow::Result<()> {
     // Configure a custom event formatter
     let format = tracing_subscriber::fmt::format()
@@ -303,3 +698,96 @@ async fn fetch_pem_data(config: &Config) -> anyhow::Result<Vec<u8>> {
         None => std::env::var("GITHUB_PRIVATE_KEY")?.into_bytes(),
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::{PrOwnership, previous_requirement, render_comment, requirement_changed_from};
+    use min_review_bot::{
+        conditional::{Approvals, OwnersConditional},
+        config::Config,
+        provenance::TrackedConditional,
+        rules::CodeownersRules,
+    };
+
+    const FILES: [&str; 2] = ["src/lib.rs", "docs/README.md"];
+
+    fn parse(s: &str) -> OwnersConditional {
+        s.parse().expect("valid conditional")
+    }
+
+    fn ownership(codeowners: &CodeownersRules) -> PrOwnership {
+        let tracked = TrackedConditional::from_codeowners(codeowners, &FILES).reduce();
+        PrOwnership {
+            conditional: tracked.conditional(),
+            tracked,
+            changed_files: FILES.iter().map(|file| file.to_string()).collect(),
+            approvals: Approvals::from_logins(Vec::<String>::new()),
+            team_members: None,
+            ownership_diff: None,
+        }
+    }
+
+    /// The comment the bot leaves given the last one it left, along with the
+    /// requirement change it notes, the same way `update_pr` works it out.
+    fn next_comment(
+        config: &Config,
+        codeowners: &CodeownersRules,
+        previous_body: Option<&str>,
+        noted: Option<(OwnersConditional, OwnersConditional)>,
+    ) -> anyhow::Result<(String, Option<(OwnersConditional, OwnersConditional)>)> {
+        let ownership = ownership(codeowners);
+        let previous = previous_body.and_then(previous_requirement);
+        let changed_from = requirement_changed_from(previous, noted, &ownership.conditional);
+        let comment = render_comment(config, None, codeowners, &ownership, changed_from.as_ref())?;
+        let noted = changed_from.map(|changed_from| (changed_from, ownership.conditional));
+        Ok((comment, noted))
+    }
+
+    #[test]
+    fn test_requirement_change_note() -> anyhow::Result<()> {
+        let config: Config = toml::de::from_str(
+            r#"
+            users = []
+            repo = "org/repo"
+            bot_username = "bot"
+            sleep_period = { secs = 60, nanos = 0 }
+            db_path = "data.db"
+            banned_prs = []
+
+            [github]
+            app_id = 1
+            "#,
+        )?;
+        let before = CodeownersRules::parse("/src/ @a @b\n/docs/ @c\n");
+        let after = CodeownersRules::parse("/src/ @a\n/docs/ @c\n");
+
+        let (first, noted) = next_comment(&config, &before, None, None)?;
+        assert!(!first.contains("Requirements changed"));
+        assert_eq!(noted, None);
+
+        let (changed, noted) = next_comment(&config, &after, Some(&first), noted)?;
+        assert!(changed.contains("> - No longer required: `(@a || @b)`"));
+        assert_eq!(noted, Some((parse("@c && (@a || @b)"), parse("@a && @c"))));
+
+        // The note stays through updates that don't change the requirement,
+        // so the comment isn't edited
+        let (unchanged, noted) = next_comment(&config, &after, Some(&changed), noted)?;
+        assert_eq!(unchanged, changed);
+
+        // Nor is it edited if the requirement noted is the same, but in a
+        // different order
+        let reordered = Some((parse("(@b || @a) && @c"), parse("@c && @a")));
+        let (reordered, _) = next_comment(&config, &after, Some(&changed), reordered)?;
+        assert_eq!(reordered, changed);
+        assert_eq!(
+            requirement_changed_from(Some(parse("@c && @a")), None, &parse("@a && @c")),
+            None
+        );
+
+        // The note goes once the requirement changes again
+        let (reverted, noted) = next_comment(&config, &before, Some(&changed), noted)?;
+        assert!(reverted.contains("> - Now required: `(@a || @b)`"));
+        assert_eq!(noted, Some((parse("@a && @c"), parse("@c && (@a || @b)"))));
+        Ok(())
+    }
+}
@@ -1,15 +1,90 @@
 //! This file provides a basic interface into GitHub that can be easily replaced
 //! and mocked out for use when testing other parts of the codebase.
+use crate::{owner::Owner, ownership_diff::OwnershipDiff, rules::CodeownersRules};
 use base64::{Engine as _, engine::general_purpose::STANDARD as base64_engine};
+use http::{
+    HeaderMap, HeaderValue, StatusCode,
+    header::{ACCEPT, ETAG, IF_NONE_MATCH},
+};
 use octocrab::{
     Octocrab,
-    models::{CommentId, issues::Comment, pulls::PullRequest},
+    models::{
+        CommentId,
+        issues::Comment,
+        pulls::{PullRequest, Review, ReviewState},
+        repos::Content,
+    },
     params::State as PrState,
 };
-use std::collections::BTreeSet;
-use tracing::instrument;
+use std::collections::{BTreeMap, BTreeSet};
+use tracing::{info, instrument};
 use unidiff::{PatchSet, PatchedFile};
 
+/// Where GitHub looks for a CODEOWNERS file, in the order it looks.
+pub const CODEOWNERS_PATHS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// A file read from the repo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileData {
+    pub content: String,
+    /// SHA of the git blob, which only changes when the content does.
+    pub sha: String,
+}
+
+/// The result of asking for a file only if it changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileFetch {
+    /// The file still has the ETag we sent.
+    NotModified,
+    Modified {
+        file: FileData,
+        /// Sent back to check the file hasn't changed next time.
+        etag: Option<String>,
+    },
+}
+
+/// The result of asking for the commit a ref points at only if it moved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommitFetch {
+    /// The ref still points at the commit with the ETag we sent.
+    NotModified,
+    Modified {
+        /// Sent back to check the ref hasn't moved next time.
+        etag: Option<String>,
+    },
+}
+
+/// The CODEOWNERS file GitHub uses before and after a PR, where `None` means
+/// there isn't one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeownersChange {
+    pub base: Option<FileData>,
+    pub head: Option<FileData>,
+    /// Where `head` was read from. The ownership diff covers the files there,
+    /// so it's only the same for the same blobs at the same `head_ref`.
+    pub head_ref: String,
+}
+
+impl CodeownersChange {
+    /// The SHAs of the blobs on each side.
+    pub fn shas(&self) -> (Option<&str>, Option<&str>) {
+        (
+            self.base.as_ref().map(|file| file.sha.as_str()),
+            self.head.as_ref().map(|file| file.sha.as_str()),
+        )
+    }
+}
+
+/// Where a CODEOWNERS file was found, and the ETag it had.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeownersLocation {
+    pub path: String,
+    pub etag: Option<String>,
+    /// ETag of the commit the ref pointed at. Until it changes, nothing on the
+    /// ref did either.
+    pub commit_etag: Option<String>,
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct Repo {
     user: String,
@@ -56,29 +131,261 @@ impl<S: RepoSource> RepoConnector<S> {
             .expect("get_files_from_diff panicked")
     }
 
+    /// Reads the CODEOWNERS file at `path`, or from the first of GitHub's
+    /// standard locations that has one if `path` is `None`. Reads it from the
+    /// default branch unless given a `git_ref`.
+    #[instrument(level = "info", skip_all, fields(git_ref = ?git_ref), err)]
+    pub async fn get_codeowners_file(
+        &self,
+        path: Option<&str>,
+        git_ref: Option<&str>,
+    ) -> Result<FileData> {
+        let paths = codeowners_paths(path);
+        for path in paths.iter().copied() {
+            match self
+                .source
+                .get_file_data(path.to_string(), git_ref, &self.repo)
+                .await
+            {
+                Ok(file) => {
+                    info!(path, sha = file.sha, "found CODEOWNERS");
+                    return Ok(file);
+                }
+                Err(Error::FileNotFound(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(Error::CodeownersNotFound(
+            paths.into_iter().map(String::from).collect(),
+        ))
+    }
+
+    /// Returns the logins of everyone whose latest review on the PR is an
+    /// approval. Comments don't affect an earlier approval, but requesting
+    /// changes or having the review dismissed does.
+    #[instrument(level = "info", skip_all, fields(pr_num = num), err)]
+    pub async fn get_pr_approvers(&self, num: u64) -> Result<BTreeSet<String>> {
+        let mut latest_states = BTreeMap::new();
+        for review in self.source.list_pr_reviews(num, &self.repo).await? {
+            let (user, state) = match (review.user, review.state) {
+                (Some(user), Some(state)) => (user, state),
+                _ => continue,
+            };
+            if matches!(state, ReviewState::Commented | ReviewState::Pending) {
+                continue;
+            }
+            latest_states.insert(user.login, state);
+        }
+
+        Ok(latest_states
+            .into_iter()
+            .filter(|(_, state)| matches!(state, ReviewState::Approved))
+            .map(|(login, _)| login)
+            .collect())
+    }
+
+    /// Returns the members of `@{org}/{team}` as individual owners.
+    #[instrument(level = "info", skip(self), err)]
+    pub async fn get_team_members(&self, org: &str, team: &str) -> Result<BTreeSet<Owner>> {
+        Ok(self
+            .source
+            .list_team_members(org, team)
+            .await?
+            .into_iter()
+            .map(Owner::User)
+            .collect())
+    }
+
+    /// Returns the path of every file in the repo as of `git_ref`, or on the
+    /// default branch if that's `None`.
+    #[instrument(level = "info", skip(self), err)]
+    pub async fn get_tree_files(&self, git_ref: Option<&str>) -> Result<Vec<String>> {
+        self.source.list_tree(git_ref, &self.repo).await
+    }
+
+    /// Like `get_codeowners_file`, but only downloads the file if it changed
+    /// since it was found at `cached`. Returns where the file is now, and the
+    /// file if it changed. GitHub doesn't count requests for unchanged files
+    /// or commits against the rate limit, so if nothing was pushed to
+    /// `git_ref` this costs nothing.
+    #[instrument(level = "info", skip_all, fields(git_ref = ?git_ref), err)]
+    pub async fn get_codeowners_file_if_modified(
+        &self,
+        path: Option<&str>,
+        git_ref: Option<&str>,
+        cached: Option<&CodeownersLocation>,
+    ) -> Result<(CodeownersLocation, Option<FileData>)> {
+        let commit_etag = match self
+            .source
+            .get_commit_if_modified(
+                git_ref,
+                cached.and_then(|cached| cached.commit_etag.as_deref()),
+                &self.repo,
+            )
+            .await?
+        {
+            CommitFetch::NotModified => match cached {
+                // Nothing was pushed, so the same CODEOWNERS file is used
+                Some(cached) => {
+                    info!("no new commits");
+                    return Ok((cached.clone(), None));
+                }
+                None => None,
+            },
+            CommitFetch::Modified { etag } => etag,
+        };
+
+        let paths = codeowners_paths(path);
+        // Paths earlier in the list still need checking, in case a CODEOWNERS
+        // file that takes precedence was added
+        for path in paths.iter().copied() {
+            let etag = cached
+                .filter(|cached| cached.path == path)
+                .and_then(|cached| cached.etag.as_deref());
+            match self
+                .source
+                .get_file_data_if_modified(path.to_string(), git_ref, etag, &self.repo)
+                .await
+            {
+                Ok(FileFetch::NotModified) => {
+                    info!(path, "CODEOWNERS not modified");
+                    let location = CodeownersLocation {
+                        path: path.to_string(),
+                        etag: etag.map(str::to_string),
+                        commit_etag,
+                    };
+                    return Ok((location, None));
+                }
+                Ok(FileFetch::Modified { file, etag }) => {
+                    info!(path, sha = file.sha, "found CODEOWNERS");
+                    let location = CodeownersLocation {
+                        path: path.to_string(),
+                        etag,
+                        commit_etag,
+                    };
+                    return Ok((location, Some(file)));
+                }
+                Err(Error::FileNotFound(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(Error::CodeownersNotFound(
+            paths.into_iter().map(String::from).collect(),
+        ))
+    }
+
+    /// Finds the CODEOWNERS files GitHub uses where the PR branched off
+    /// `base_ref`, and at `head_ref`. Returns `None` if none of
+    /// `changed_files` is where CODEOWNERS is read from, or if the file GitHub
+    /// uses is the same on both sides. Comparing against where the PR branched
+    /// off leaves out changes made on `base_ref` since.
+    #[instrument(level = "info", skip(self, path, changed_files), err)]
+    pub async fn get_codeowners_change(
+        &self,
+        path: Option<&str>,
+        changed_files: &BTreeSet<String>,
+        base_ref: &str,
+        head_ref: &str,
+    ) -> Result<Option<CodeownersChange>> {
+        let paths = codeowners_paths(path);
+        if !changed_files
+            .iter()
+            .any(|file| paths.contains(&file.as_str()))
+        {
+            return Ok(None);
+        }
+        let merge_base = self
+            .source
+            .get_merge_base(base_ref, head_ref, &self.repo)
+            .await?;
+        let base = self.get_optional_codeowners_file(path, &merge_base).await?;
+        let head = self.get_optional_codeowners_file(path, head_ref).await?;
+        // The PR only edits a CODEOWNERS file GitHub doesn't use
+        if base.as_ref().map(|file| &file.sha) == head.as_ref().map(|file| &file.sha) {
+            return Ok(None);
+        }
+        Ok(Some(CodeownersChange {
+            base,
+            head,
+            head_ref: head_ref.to_string(),
+        }))
+    }
+
+    /// Works out how `change` changes the owners of the files in the repo as
+    /// of its `head_ref`. A side without a CODEOWNERS file doesn't give
+    /// anything an owner.
+    #[instrument(level = "info", skip_all, fields(head_ref = %change.head_ref), err)]
+    pub async fn get_ownership_diff(&self, change: &CodeownersChange) -> Result<OwnershipDiff> {
+        let parse = |file: &Option<FileData>| {
+            file.as_ref()
+                .map(|file| CodeownersRules::parse(&file.content))
+                .unwrap_or_default()
+        };
+        let files = self.get_tree_files(Some(&change.head_ref)).await?;
+        let files: Vec<&str> = files.iter().map(String::as_str).collect();
+        Ok(OwnershipDiff::new(
+            &parse(&change.base),
+            &parse(&change.head),
+            &files,
+        ))
+    }
+
+    async fn get_optional_codeowners_file(
+        &self,
+        path: Option<&str>,
+        git_ref: &str,
+    ) -> Result<Option<FileData>> {
+        match self.get_codeowners_file(path, Some(git_ref)).await {
+            Ok(file) => Ok(Some(file)),
+            Err(Error::CodeownersNotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the users and teams out of `owners` that don't exist on GitHub,
+    /// or that the app can't see.
     #[instrument(level = "info", skip_all, err)]
-    pub async fn get_codeowners_content(&self) -> Result<String> {
-        self.source
-            .get_file_data("docs/CODEOWNERS".into(), &self.repo)
-            .await
+    pub async fn get_missing_owners(&self, owners: &BTreeSet<Owner>) -> Result<BTreeSet<Owner>> {
+        let mut missing = BTreeSet::new();
+        for owner in owners {
+            if !self.source.owner_exists(owner).await? {
+                missing.insert(owner.clone());
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Fetches the PR, for its author and the branches it's between.
+    #[instrument(level = "info", skip_all, fields(pr_num = num), err)]
+    pub async fn get_pr(&self, num: u64) -> Result<PullRequest> {
+        self.source.get_pr(num, &self.repo).await
     }
 
     pub async fn get_open_prs(&self) -> Result<Vec<PullRequest>> {
         self.source.list_open_prs(&self.repo).await
     }
 
-    pub async fn add_or_edit_comment(
+    /// Finds the comment previously left on the PR by `bot_username`, if any.
+    pub async fn get_bot_comment(
         &self,
         pr_num: u64,
-        comment_body: String,
-        bot_username: String,
-    ) -> Result {
-        let comment = self
+        bot_username: &str,
+    ) -> Result<Option<Comment>> {
+        Ok(self
             .source
             .list_pr_comments(pr_num, &self.repo)
             .await?
             .into_iter()
-            .find(|comment| comment.user.login == bot_username);
+            .find(|comment| comment.user.login == bot_username))
+    }
+
+    pub async fn add_or_edit_comment(
+        &self,
+        pr_num: u64,
+        comment_body: String,
+        bot_username: String,
+    ) -> Result {
+        let comment = self.get_bot_comment(pr_num, &bot_username).await?;
 
         match comment {
             Some(comment) => {
@@ -102,9 +409,77 @@ pub trait RepoSource {
     async fn add_pr_comment(&self, body: String, num: u64, repo: &Repo) -> Result<Comment>;
     async fn edit_pr_comment(&self, body: String, comment_id: u64, repo: &Repo) -> Result<Comment>;
     async fn list_pr_comments(&self, num: u64, repo: &Repo) -> Result<Vec<Comment>>;
+    async fn list_pr_reviews(&self, num: u64, repo: &Repo) -> Result<Vec<Review>>;
     async fn get_pr_diff(&self, num: u64, repo: &Repo) -> Result<String>;
-    async fn get_file_data(&self, path: String, repo: &Repo) -> Result<String>;
+    /// Reads the file at `path` as of `git_ref`, or on the default branch if
+    /// that's `None`.
+    async fn get_file_data(
+        &self,
+        path: String,
+        git_ref: Option<&str>,
+        repo: &Repo,
+    ) -> Result<FileData>;
+    /// Like `get_file_data`, but doesn't download the file if its ETag is
+    /// still `etag`.
+    async fn get_file_data_if_modified(
+        &self,
+        path: String,
+        git_ref: Option<&str>,
+        etag: Option<&str>,
+        repo: &Repo,
+    ) -> Result<FileFetch>;
+    /// Checks whether `git_ref`, or the default branch if that's `None`, still
+    /// points at the commit whose ETag is `etag`.
+    async fn get_commit_if_modified(
+        &self,
+        git_ref: Option<&str>,
+        etag: Option<&str>,
+        repo: &Repo,
+    ) -> Result<CommitFetch>;
+    /// Lists the path of every file in the repo as of `git_ref`, or on the
+    /// default branch if that's `None`.
+    async fn list_tree(&self, git_ref: Option<&str>, repo: &Repo) -> Result<Vec<String>>;
+    /// Returns the SHA of the last commit `head` and `base` have in common.
+    async fn get_merge_base(&self, base: &str, head: &str, repo: &Repo) -> Result<String>;
     async fn list_open_prs(&self, repo: &Repo) -> Result<Vec<PullRequest>>;
+    async fn get_pr(&self, num: u64, repo: &Repo) -> Result<PullRequest>;
+    /// Lists the logins of everyone on the team with slug `team` in `org`.
+    async fn list_team_members(&self, org: &str, team: &str) -> Result<Vec<String>>;
+    /// Whether the user or team exists. Emails can't be looked up, so they
+    /// always do.
+    async fn owner_exists(&self, owner: &Owner) -> Result<bool>;
+}
+
+/// The only parts of the git trees API response we care about.
+#[derive(Debug, serde::Deserialize)]
+struct GitTree {
+    tree: Vec<GitTreeEntry>,
+    truncated: bool,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct GitTreeEntry {
+    path: String,
+    /// `blob` for files, `tree` for directories and `commit` for submodules.
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+/// The only part of the compare API response we care about.
+#[derive(Debug, serde::Deserialize)]
+struct Comparison {
+    merge_base_commit: CommitRef,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CommitRef {
+    sha: String,
+}
+
+/// The only part of the team members API response we care about.
+#[derive(Debug, serde::Deserialize)]
+struct TeamMember {
+    login: String,
 }
 
 #[derive(Debug)]
@@ -191,6 +566,31 @@ impl RepoSource for GithubSource {
         Ok(comments)
     }
 
+    #[instrument(level = "debug", err)]
+    async fn list_pr_reviews(&self, num: u64, repo: &Repo) -> Result<Vec<Review>> {
+        let mut reviews = vec![];
+        let mut page_num = 1u32;
+
+        loop {
+            let mut new_reviews = self
+                .octo_instance
+                .pulls(repo.user(), repo.repo())
+                .list_reviews(num)
+                .per_page(100)
+                .page(page_num)
+                .send()
+                .await?
+                .take_items();
+            if new_reviews.is_empty() {
+                break;
+            }
+            page_num += 1;
+            reviews.append(&mut new_reviews);
+        }
+
+        Ok(reviews)
+    }
+
     #[instrument(level = "info", err)]
     async fn get_pr_diff(&self, num: u64, repo: &Repo) -> Result<String> {
         Ok(self
@@ -202,14 +602,29 @@ impl RepoSource for GithubSource {
     }
 
     #[instrument(level = "debug", err)]
-    async fn get_file_data(&self, path: String, repo: &Repo) -> Result<String> {
-        let content_items = self
+    async fn get_file_data(
+        &self,
+        path: String,
+        git_ref: Option<&str>,
+        repo: &Repo,
+    ) -> Result<FileData> {
+        let mut request = self
             .octo_instance
             .repos(repo.user(), repo.repo())
             .get_content()
-            .path(path)
+            .path(&path);
+        if let Some(git_ref) = git_ref {
+            request = request.r#ref(git_ref);
+        }
+        let content_items = request
             .send()
-            .await?
+            .await
+            .map_err(|e| match e {
+                octocrab::Error::GitHub { source, .. } if source.status_code.as_u16() == 404 => {
+                    Error::FileNotFound(path.clone())
+                }
+                e => e.into(),
+            })?
             .take_items();
 
         if content_items.len() != 1 {
@@ -222,7 +637,162 @@ impl RepoSource for GithubSource {
             .ok_or(Error::EmptyContents)?
             .replace('\n', "");
 
-        Ok(String::from_utf8(base64_engine.decode(raw_contents)?)?)
+        Ok(FileData {
+            content: String::from_utf8(base64_engine.decode(raw_contents)?)?,
+            sha: content_items[0].sha.clone(),
+        })
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn get_file_data_if_modified(
+        &self,
+        path: String,
+        git_ref: Option<&str>,
+        etag: Option<&str>,
+        repo: &Repo,
+    ) -> Result<FileFetch> {
+        // octocrab's content API doesn't let us send headers
+        let mut route = format!(
+            "/repos/{}/{}/contents/{}",
+            repo.user(),
+            repo.repo(),
+            path.split('/')
+                .map(encode_url_component)
+                .collect::<Vec<_>>()
+                .join("/")
+        );
+        if let Some(git_ref) = git_ref {
+            route.push_str(&format!("?ref={}", encode_url_component(git_ref)));
+        }
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = etag
+            && let Ok(etag) = HeaderValue::from_str(etag)
+        {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        let response = self
+            .octo_instance
+            ._get_with_headers(route, Some(headers))
+            .await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(FileFetch::NotModified);
+        }
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_string);
+        let response = octocrab::map_github_error(response)
+            .await
+            .map_err(|e| match e {
+                octocrab::Error::GitHub { source, .. } if source.status_code.as_u16() == 404 => {
+                    Error::FileNotFound(path.clone())
+                }
+                e => e.into(),
+            })?;
+        let content: Content =
+            serde_json::from_str(&self.octo_instance.body_to_string(response).await?)?;
+
+        let raw_contents = content
+            .content
+            .ok_or(Error::EmptyContents)?
+            .replace('\n', "");
+        Ok(FileFetch::Modified {
+            file: FileData {
+                content: String::from_utf8(base64_engine.decode(raw_contents)?)?,
+                sha: content.sha,
+            },
+            etag,
+        })
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn get_commit_if_modified(
+        &self,
+        git_ref: Option<&str>,
+        etag: Option<&str>,
+        repo: &Repo,
+    ) -> Result<CommitFetch> {
+        let route = format!(
+            "/repos/{}/{}/commits/{}",
+            repo.user(),
+            repo.repo(),
+            encode_url_component(git_ref.unwrap_or("HEAD"))
+        );
+        let mut headers = HeaderMap::new();
+        // Just the SHA rather than the whole commit, since all we want to know
+        // is whether it changed
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_static("application/vnd.github.sha"),
+        );
+        if let Some(etag) = etag
+            && let Ok(etag) = HeaderValue::from_str(etag)
+        {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        let response = self
+            .octo_instance
+            ._get_with_headers(route, Some(headers))
+            .await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(CommitFetch::NotModified);
+        }
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_string);
+        octocrab::map_github_error(response).await?;
+        Ok(CommitFetch::Modified { etag })
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn list_tree(&self, git_ref: Option<&str>, repo: &Repo) -> Result<Vec<String>> {
+        let tree_ref = git_ref.unwrap_or("HEAD");
+        let tree: GitTree = self
+            .octo_instance
+            .get(
+                // Branch names can contain slashes, which would otherwise be
+                // read as part of the route
+                format!(
+                    "/repos/{}/{}/git/trees/{}",
+                    repo.user(),
+                    repo.repo(),
+                    encode_url_component(tree_ref)
+                ),
+                Some(&[("recursive", "1")]),
+            )
+            .await?;
+        if tree.truncated {
+            return Err(Error::TruncatedTree(tree_ref.to_string()));
+        }
+
+        Ok(tree
+            .tree
+            .into_iter()
+            .filter(|entry| entry.kind == "blob")
+            .map(|entry| entry.path)
+            .collect())
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn get_merge_base(&self, base: &str, head: &str, repo: &Repo) -> Result<String> {
+        let comparison: Comparison = self
+            .octo_instance
+            .get(
+                format!(
+                    "/repos/{}/{}/compare/{}...{}",
+                    repo.user(),
+                    repo.repo(),
+                    encode_url_component(base),
+                    encode_url_component(head)
+                ),
+                // We only need the merge base, not the commits in between
+                Some(&[("per_page", 1)]),
+            )
+            .await?;
+        Ok(comparison.merge_base_commit.sha)
     }
 
     #[instrument(level = "debug", err)]
@@ -251,6 +821,64 @@ impl RepoSource for GithubSource {
 
         Ok(prs)
     }
+
+    #[instrument(level = "debug", err)]
+    async fn get_pr(&self, num: u64, repo: &Repo) -> Result<PullRequest> {
+        Ok(self
+            .octo_instance
+            .pulls(repo.user(), repo.repo())
+            .get(num)
+            .await?)
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn list_team_members(&self, org: &str, team: &str) -> Result<Vec<String>> {
+        let mut members = vec![];
+        let mut page_num = 1u32;
+
+        loop {
+            let new_members: Vec<TeamMember> = self
+                .octo_instance
+                .get(
+                    format!(
+                        "/orgs/{}/teams/{}/members",
+                        encode_url_component(org),
+                        encode_url_component(team)
+                    ),
+                    Some(&[("per_page", 100u32), ("page", page_num)]),
+                )
+                .await?;
+            if new_members.is_empty() {
+                break;
+            }
+            page_num += 1;
+            members.extend(new_members.into_iter().map(|member| member.login));
+        }
+
+        Ok(members)
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn owner_exists(&self, owner: &Owner) -> Result<bool> {
+        let route = match owner {
+            Owner::User(login) => format!("/users/{}", encode_url_component(login)),
+            Owner::Team { org, team } => format!(
+                "/orgs/{}/teams/{}",
+                encode_url_component(org),
+                encode_url_component(team)
+            ),
+            Owner::Email(_) => return Ok(true),
+        };
+        let response: Result<serde_json::Value, _> =
+            self.octo_instance.get(route, None::<&()>).await;
+        match response {
+            Ok(_) => Ok(true),
+            Err(octocrab::Error::GitHub { source, .. }) if source.status_code.as_u16() == 404 => {
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -261,6 +889,14 @@ pub enum Error {
     GotMulticontent,
     #[error("requested file contents returned empty")]
     EmptyContents,
+    #[error("file {0} does not exist")]
+    FileNotFound(String),
+    #[error("{0} does not exist")]
+    NotFound(String),
+    #[error("no CODEOWNERS file found, looked in {}", .0.join(", "))]
+    CodeownersNotFound(Vec<String>),
+    #[error("the tree at {0} has too many files to list")]
+    TruncatedTree(String),
     #[error("error talking to github api: {0}")]
     OctocrabError(#[from] octocrab::Error),
     #[error("error parsing diff: {0}")]
@@ -273,10 +909,35 @@ pub enum Error {
     Base64Decode(#[from] base64::DecodeError),
     #[error("could not decode file as utf8: {0}")]
     InvalidUtf8(#[from] std::string::FromUtf8Error),
+    #[error("could not parse github response: {0}")]
+    InvalidResponse(#[from] serde_json::Error),
 }
 
 pub type Result<T = (), E = Error> = std::result::Result<T, E>;
 
+/// Percent-encodes everything but unreserved characters, so branch names and
+/// file names can go in a route or query without changing its meaning.
+fn encode_url_component(component: &str) -> String {
+    component
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Where to look for CODEOWNERS: `path` if given, or else wherever GitHub
+/// does.
+fn codeowners_paths(path: Option<&str>) -> Vec<&str> {
+    match path {
+        Some(path) => vec![path],
+        None => CODEOWNERS_PATHS.to_vec(),
+    }
+}
+
 #[instrument(level = "info", skip_all, err)]
 fn get_files_from_diff(diff: String) -> Result<BTreeSet<String>> {
     let mut files_changed = BTreeSet::new();
@@ -331,3 +992,430 @@ fn remove_prefix(is_target: bool) -> Box<PatchedFileRemoveFn> {
         }
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        CodeownersLocation, CommitFetch, Error, FileData, FileFetch, Repo, RepoConnector,
+        RepoSource, Result, encode_url_component,
+    };
+    use crate::{
+        conditional::{OwnersConditional, SolveMode},
+        owner::{Owner, TeamMembers},
+        ownership_diff::OwnershipChange,
+    };
+    use octocrab::models::{
+        issues::Comment,
+        pulls::{PullRequest, Review},
+    };
+    use std::{
+        collections::BTreeMap,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    /// Only knows about files and team memberships, which is all these tests
+    /// need. The repo has no PRs, so there's nothing to comment on.
+    #[derive(Default)]
+    struct FakeSource {
+        files: BTreeMap<String, String>,
+        teams: BTreeMap<(String, String), Vec<String>>,
+        /// Merge bases by `(base, head)`. Other branches branched off the tip
+        /// of their base.
+        merge_bases: BTreeMap<(String, String), String>,
+        /// How many times a file was asked for only if it changed.
+        file_requests: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl RepoSource for FakeSource {
+        async fn add_pr_comment(&self, _body: String, num: u64, _repo: &Repo) -> Result<Comment> {
+            Err(Error::NotFound(format!("pull request {num}")))
+        }
+        async fn edit_pr_comment(
+            &self,
+            _body: String,
+            comment_id: u64,
+            _repo: &Repo,
+        ) -> Result<Comment> {
+            Err(Error::NotFound(format!("comment {comment_id}")))
+        }
+        async fn list_pr_comments(&self, num: u64, _repo: &Repo) -> Result<Vec<Comment>> {
+            Err(Error::NotFound(format!("pull request {num}")))
+        }
+        async fn list_pr_reviews(&self, num: u64, _repo: &Repo) -> Result<Vec<Review>> {
+            Err(Error::NotFound(format!("pull request {num}")))
+        }
+        async fn get_pr_diff(&self, num: u64, _repo: &Repo) -> Result<String> {
+            Err(Error::NotFound(format!("pull request {num}")))
+        }
+        async fn get_file_data(
+            &self,
+            path: String,
+            git_ref: Option<&str>,
+            _repo: &Repo,
+        ) -> Result<FileData> {
+            // Files on other branches are stored under `<ref>:<path>`
+            let key = match git_ref {
+                Some(git_ref) => format!("{git_ref}:{path}"),
+                None => path.clone(),
+            };
+            let content = self
+                .files
+                .get(&key)
+                .cloned()
+                .ok_or(Error::FileNotFound(path))?;
+            Ok(FileData {
+                sha: format!("sha of {content}"),
+                content,
+            })
+        }
+        async fn get_file_data_if_modified(
+            &self,
+            path: String,
+            git_ref: Option<&str>,
+            etag: Option<&str>,
+            repo: &Repo,
+        ) -> Result<FileFetch> {
+            self.file_requests.fetch_add(1, Ordering::Relaxed);
+            let file = self.get_file_data(path, git_ref, repo).await?;
+            let file_etag = format!("etag of {}", file.content);
+            if etag == Some(file_etag.as_str()) {
+                return Ok(FileFetch::NotModified);
+            }
+            Ok(FileFetch::Modified {
+                file,
+                etag: Some(file_etag),
+            })
+        }
+        async fn get_commit_if_modified(
+            &self,
+            git_ref: Option<&str>,
+            etag: Option<&str>,
+            repo: &Repo,
+        ) -> Result<CommitFetch> {
+            // The commit changes whenever any file on the ref does
+            let mut commit_etag = "etag of commit with".to_string();
+            for path in self.list_tree(git_ref, repo).await? {
+                let file = self.get_file_data(path.clone(), git_ref, repo).await?;
+                commit_etag.push_str(&format!(" {path}={}", file.sha));
+            }
+            if etag == Some(commit_etag.as_str()) {
+                return Ok(CommitFetch::NotModified);
+            }
+            Ok(CommitFetch::Modified {
+                etag: Some(commit_etag),
+            })
+        }
+        async fn list_tree(&self, git_ref: Option<&str>, _repo: &Repo) -> Result<Vec<String>> {
+            Ok(self
+                .files
+                .keys()
+                .filter_map(|key| match (git_ref, key.split_once(':')) {
+                    (Some(git_ref), Some((file_ref, path))) if file_ref == git_ref => {
+                        Some(path.to_string())
+                    }
+                    (None, None) => Some(key.clone()),
+                    _ => None,
+                })
+                .collect())
+        }
+        async fn get_merge_base(&self, base: &str, head: &str, _repo: &Repo) -> Result<String> {
+            Ok(self
+                .merge_bases
+                .get(&(base.to_string(), head.to_string()))
+                .cloned()
+                .unwrap_or_else(|| base.to_string()))
+        }
+        async fn list_open_prs(&self, _repo: &Repo) -> Result<Vec<PullRequest>> {
+            Ok(Vec::new())
+        }
+        async fn get_pr(&self, num: u64, _repo: &Repo) -> Result<PullRequest> {
+            Err(Error::NotFound(format!("pull request {num}")))
+        }
+        async fn list_team_members(&self, org: &str, team: &str) -> Result<Vec<String>> {
+            Ok(self
+                .teams
+                .get(&(org.to_string(), team.to_string()))
+                .cloned()
+                .unwrap_or_default())
+        }
+        async fn owner_exists(&self, owner: &Owner) -> Result<bool> {
+            // Users exist if they're on a team, and teams if they're known
+            Ok(match owner {
+                Owner::User(login) => self.teams.values().flatten().any(|member| member == login),
+                Owner::Team { org, team } => self.teams.contains_key(&(org.clone(), team.clone())),
+                Owner::Email(_) => false,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_team_expansion() -> anyhow::Result<()> {
+        let source = FakeSource {
+            teams: [
+                (("org", "backend"), vec!["bob", "carol"]),
+                (("org", "infra"), vec!["dave", "bob"]),
+                (("org", "web"), vec!["bob"]),
+            ]
+            .into_iter()
+            .map(|((org, team), members)| {
+                (
+                    (org.to_string(), team.to_string()),
+                    members.into_iter().map(String::from).collect(),
+                )
+            })
+            .collect(),
+            ..FakeSource::default()
+        };
+        let connector = RepoConnector::new(source, Repo::new("org".into(), "repo".into()));
+        let conditional: OwnersConditional =
+            "(@alice || @org/backend) && @org/infra && @org/web".parse()?;
+
+        let mut members = TeamMembers::default();
+        for team in conditional.teams() {
+            if let Owner::Team { org, team: slug } = &team {
+                members.insert(team.clone(), connector.get_team_members(org, slug).await?);
+            }
+        }
+        assert_eq!(
+            members.teams_of(&Owner::from("@bob")).len(),
+            3,
+            "bob is on every team"
+        );
+
+        // Without expansion every team needs its own reviewer
+        assert_eq!(
+            conditional
+                .minimum_owner_sets(SolveMode::Exact)
+                .sets
+                .first()
+                .map(|set| set.len()),
+            Some(3)
+        );
+        let minimum = conditional
+            .clone()
+            .expand_teams(&members)
+            .reduce()
+            .minimum_owner_sets(SolveMode::Exact);
+        assert_eq!(minimum.sets, vec![[Owner::from("@bob")].into()]);
+        assert_eq!(
+            crate::display_team_coverage(&conditional, &minimum, &members),
+            "- `@bob` alone satisfies all 3 team requirements\n"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_codeowners_discovery() -> anyhow::Result<()> {
+        let connector = |files: &[&str]| {
+            let source = FakeSource {
+                files: files
+                    .iter()
+                    .map(|path| (path.to_string(), format!("* @{path}")))
+                    .collect(),
+                ..FakeSource::default()
+            };
+            RepoConnector::new(source, Repo::new("org".into(), "repo".into()))
+        };
+
+        // GitHub prefers .github/, then the root, then docs/
+        let all = connector(&["docs/CODEOWNERS", "CODEOWNERS", ".github/CODEOWNERS"]);
+        assert_eq!(
+            all.get_codeowners_file(None, None).await?.content,
+            "* @.github/CODEOWNERS"
+        );
+        let docs = connector(&["docs/CODEOWNERS", "CODEOWNERS", "release:docs/CODEOWNERS"]);
+        assert_eq!(
+            docs.get_codeowners_file(None, None).await?.content,
+            "* @CODEOWNERS"
+        );
+        assert_eq!(
+            docs.get_codeowners_file(Some("docs/CODEOWNERS"), None)
+                .await?
+                .content,
+            "* @docs/CODEOWNERS"
+        );
+        assert_eq!(
+            docs.get_codeowners_file(None, Some("release"))
+                .await?
+                .content,
+            "* @release:docs/CODEOWNERS"
+        );
+
+        let none = connector(&[]);
+        assert_eq!(
+            none.get_codeowners_file(None, None)
+                .await
+                .unwrap_err()
+                .to_string(),
+            "no CODEOWNERS file found, looked in .github/CODEOWNERS, CODEOWNERS, docs/CODEOWNERS"
+        );
+        assert!(matches!(
+            none.get_codeowners_file(Some("OWNERS"), None).await,
+            Err(Error::CodeownersNotFound(paths)) if paths == ["OWNERS"]
+        ));
+
+        // Unchanged files aren't downloaded again
+        let (location, file) = docs
+            .get_codeowners_file_if_modified(None, None, None)
+            .await?;
+        assert_eq!(location.path, "CODEOWNERS");
+        assert_eq!(location.etag, Some("etag of * @CODEOWNERS".into()));
+        assert_eq!(file.map(|file| file.content), Some("* @CODEOWNERS".into()));
+        assert_eq!(
+            docs.get_codeowners_file_if_modified(None, None, Some(&location))
+                .await?,
+            (location.clone(), None)
+        );
+        // Nor are the paths that take precedence checked again until something
+        // is pushed
+        let file_requests = || docs.source.file_requests.load(Ordering::Relaxed);
+        let before = file_requests();
+        docs.get_codeowners_file_if_modified(None, None, Some(&location))
+            .await?;
+        assert_eq!(file_requests(), before);
+        let pushed = CodeownersLocation {
+            commit_etag: Some("etag of an older commit".into()),
+            ..location.clone()
+        };
+        assert_eq!(
+            docs.get_codeowners_file_if_modified(None, None, Some(&pushed))
+                .await?,
+            (location.clone(), None)
+        );
+        assert_eq!(file_requests(), before + 2);
+        // A file that takes precedence is picked up once it's pushed
+        assert_eq!(
+            all.get_codeowners_file_if_modified(None, None, Some(&location))
+                .await?
+                .0
+                .path,
+            ".github/CODEOWNERS"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ownership_diff() -> anyhow::Result<()> {
+        let source = FakeSource {
+            files: [
+                // main gained a rule after head branched off at fork
+                ("fork:CODEOWNERS", "/src/ @a\n"),
+                ("main:CODEOWNERS", "/src/ @a\n/docs/ @main\n"),
+                ("main:src/lib.rs", ""),
+                ("head:CODEOWNERS", "/src/ @a\n/src/api/ @api\n"),
+                ("head:docs/CODEOWNERS", "/docs/ @docs\n"),
+                ("head:docs/index.md", ""),
+                ("head:src/lib.rs", ""),
+                ("head:src/api/mod.rs", ""),
+                // A later push to head that only adds a file
+                ("pushed:CODEOWNERS", "/src/ @a\n/src/api/ @api\n"),
+                ("pushed:docs/CODEOWNERS", "/docs/ @docs\n"),
+                ("pushed:docs/index.md", ""),
+                ("pushed:src/lib.rs", ""),
+                ("pushed:src/api/mod.rs", ""),
+                ("pushed:src/api/auth.rs", ""),
+                ("docs:CODEOWNERS", "/src/ @a\n/docs/ @main\n"),
+                ("docs:docs/CODEOWNERS", "/docs/ @docs\n"),
+            ]
+            .into_iter()
+            .map(|(path, content)| (path.to_string(), content.to_string()))
+            .collect(),
+            merge_bases: [
+                (("main".into(), "head".into()), "fork".into()),
+                (("main".into(), "pushed".into()), "fork".into()),
+            ]
+            .into(),
+            ..FakeSource::default()
+        };
+        let connector = RepoConnector::new(source, Repo::new("org".into(), "repo".into()));
+        let changed = |files: &[&str]| files.iter().map(|file| file.to_string()).collect();
+
+        let change = connector
+            .get_codeowners_change(None, &changed(&["CODEOWNERS"]), "main", "head")
+            .await?
+            .expect("CODEOWNERS changed");
+        assert_eq!(
+            change.shas(),
+            (
+                Some("sha of /src/ @a\n"),
+                Some("sha of /src/ @a\n/src/api/ @api\n")
+            )
+        );
+        // The rule main added since doesn't show up as docs/ losing owners
+        let diff = connector.get_ownership_diff(&change).await?;
+        assert_eq!(
+            diff.changed,
+            vec![OwnershipChange {
+                path: "src/api/mod.rs".into(),
+                before: [Owner::from("@a")].into(),
+                after: [Owner::from("@api")].into(),
+            }]
+        );
+        assert!(diff.gained.is_empty() && diff.lost.is_empty());
+        assert_eq!(
+            crate::display_codeowners_changes(Some(&diff)),
+            "## CODEOWNERS changes\nThis PR edits CODEOWNERS, which changes the owners of 1 file once it's merged:\n- `src/api/mod.rs` changes owners from `@a` to `@api`\n\n"
+        );
+        // Files added later change the diff even though CODEOWNERS didn't
+        let pushed = connector
+            .get_codeowners_change(None, &changed(&["CODEOWNERS"]), "main", "pushed")
+            .await?
+            .expect("CODEOWNERS changed");
+        assert_eq!(pushed.shas(), change.shas());
+        assert_ne!(pushed, change);
+        assert_eq!(
+            connector
+                .get_ownership_diff(&pushed)
+                .await?
+                .changed
+                .iter()
+                .map(|change| change.path.as_str())
+                .collect::<Vec<_>>(),
+            ["src/api/auth.rs", "src/api/mod.rs"]
+        );
+
+        // GitHub uses the root CODEOWNERS over docs/, or the PR doesn't
+        // change CODEOWNERS at all
+        assert_eq!(
+            connector
+                .get_codeowners_change(None, &changed(&["docs/CODEOWNERS"]), "main", "docs")
+                .await?,
+            None
+        );
+        assert_eq!(
+            connector
+                .get_codeowners_change(None, &changed(&["src/lib.rs"]), "main", "head")
+                .await?,
+            None
+        );
+        // Adding CODEOWNERS gives files owners
+        let change = connector
+            .get_codeowners_change(None, &changed(&["CODEOWNERS"]), "none", "main")
+            .await?
+            .expect("CODEOWNERS added");
+        assert_eq!(change.base, None);
+        let diff = connector.get_ownership_diff(&change).await?;
+        assert_eq!(diff.gained.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_url_component() {
+        assert_eq!(encode_url_component("release-1.2_x~"), "release-1.2_x~");
+        assert_eq!(
+            encode_url_component("fix/#12 100%&a+b=ü"),
+            "fix%2F%2312%20100%25%26a%2Bb%3D%C3%BC"
+        );
+        // Owner names come from CODEOWNERS, so must not be able to change the
+        // route either
+        assert_eq!(
+            encode_url_component("../teams?page=2#x"),
+            "..%2Fteams%3Fpage%3D2%23x"
+        );
+        assert_eq!(encode_url_component("bob/repos"), "bob%2Frepos");
+    }
+}
@@ -1,12 +1,52 @@
-use codeowners::Owners;
+use conditional::{
+    Approvals, ConditionalDiff, Evaluation, MinimumOwnerSets, OwnersConditional, ParseError,
+    WeightedOwnerSet,
+};
+use config::UnownedFiles;
+use owner::{Owner, TeamMembers};
+use ownership_diff::{OwnershipChange, OwnershipDiff};
+use provenance::{TrackedClause, TrackedConditional};
+use render::RenderFormat;
+use rules::CodeownersRules;
+use std::collections::BTreeSet;
 
 pub mod cache;
+pub mod codeowners;
 pub mod conditional;
 pub mod config;
+pub mod coverage;
 pub mod github;
+pub mod lint;
 pub mod metrics;
+pub mod owner;
+pub mod owner_set;
+pub mod ownership_diff;
+pub mod provenance;
+pub mod render;
+pub mod report;
+pub mod rules;
+pub mod who_owns;
 
-pub fn display_file_owners(codeowners: &Owners, files: &[&str]) -> String {
+/// Most files listed in the CODEOWNERS changes section, to keep the comment
+/// readable.
+const MAX_OWNERSHIP_CHANGES: usize = 50;
+
+/// Line of the bot comment right before the requirement expression.
+const REQUIREMENT_HEADER: &str = "The minimum set of reviewers required are:";
+
+/// Reads the requirement back out of a comment previously left by the bot.
+/// Returns `None` if the comment doesn't contain one.
+pub fn requirement_from_comment(body: &str) -> Option<Result<OwnersConditional, ParseError>> {
+    let line = body
+        .lines()
+        .skip_while(|line| line.trim() != REQUIREMENT_HEADER)
+        .nth(1)?
+        .trim();
+    let expression = line.strip_prefix('`')?.strip_suffix('`')?;
+    Some(expression.parse())
+}
+
+pub fn display_file_owners(codeowners: &CodeownersRules, files: &[&str]) -> String {
     let mut display_str = "<ul>".to_string();
     let owners_map = conditional::to_owners_map(codeowners, files);
     for (file, owners) in owners_map.into_iter() {
@@ -26,3 +66,451 @@ pub fn display_file_owners(codeowners: &Owners, files: &[&str]) -> String {
 
     display_str
 }
+
+pub fn display_minimum_owner_sets(minimum: &MinimumOwnerSets) -> String {
+    if minimum.sets.is_empty() {
+        return "No set of reviewers can satisfy these requirements".to_string();
+    }
+    if minimum.sets.iter().all(|set| set.is_empty()) {
+        return "No reviewers are required".to_string();
+    }
+
+    let options = minimum
+        .sets
+        .iter()
+        .map(|set| {
+            set.iter()
+                .map(|owner| format!("`{owner}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .collect::<Vec<_>>()
+        .join(" *or* ");
+    if minimum.exact {
+        options
+    } else {
+        format!("{options} (approximate, this PR is too large to search exhaustively)")
+    }
+}
+
+pub fn display_weighted_owner_set(weighted: Option<&WeightedOwnerSet>) -> String {
+    let weighted = match weighted {
+        Some(weighted) => weighted,
+        None => {
+            return "No set of reviewers can satisfy these requirements".to_string();
+        }
+    };
+    if weighted.owners.is_empty() {
+        return "No reviewers are required".to_string();
+    }
+
+    let costs = weighted
+        .owners
+        .iter()
+        .map(|(owner, cost)| format!("`{owner}` (cost {cost})"))
+        .collect::<Vec<_>>()
+        .join(" + ");
+    let approximate = if weighted.exact {
+        ""
+    } else {
+        " (approximate, this PR is too large to search exhaustively)"
+    };
+    format!("{costs} = total cost {}{approximate}", weighted.cost)
+}
+
+/// The changed files that keep the PR from being merged however it's
+/// approved. Only unowned files do, and only with `UnownedFiles::Block`.
+pub fn blocked_files(tracked: &TrackedConditional, policy: &UnownedFiles) -> BTreeSet<String> {
+    match policy {
+        UnownedFiles::Block => tracked.unowned_files.clone(),
+        UnownedFiles::Allow | UnownedFiles::Fallback(_) => BTreeSet::new(),
+    }
+}
+
+/// Shows which requirements are met by `approvals`. The PR is reported as
+/// blocked while any of `blocked_files` are changed, since no approval can
+/// satisfy those.
+pub fn display_approval_status(
+    conditional: &OwnersConditional,
+    approvals: &Approvals,
+    blocked_files: &BTreeSet<String>,
+) -> String {
+    let summary = match conditional.evaluate(approvals) {
+        _ if !blocked_files.is_empty() => format!(
+            "Blocked: {} unowned file(s) need an owner in CODEOWNERS before this PR is merged",
+            blocked_files.len()
+        ),
+        Evaluation::Satisfied => "All ownership requirements are satisfied".to_string(),
+        Evaluation::Outstanding(residual) => format!("Still needed: `{residual}`"),
+    };
+    let clause_lines = conditional
+        .conjuncts()
+        .iter()
+        .map(|clause| {
+            let state = match clause.evaluate(approvals) {
+                Evaluation::Satisfied => "✅",
+                Evaluation::Outstanding(_) => "⏳",
+            };
+            format!("- {state} `{clause}`")
+        })
+        .collect::<Vec<_>>();
+
+    format!("{summary}\n{}", clause_lines.join("\n"))
+}
+
+/// Renders the requirement in `format`, to show under the expression in the
+/// comment. Returns an empty string for `RenderFormat::Expression`, since the
+/// expression is always shown.
+pub fn display_rendered_requirement(
+    conditional: &OwnersConditional,
+    format: RenderFormat,
+    approvals: &Approvals,
+) -> String {
+    match format {
+        RenderFormat::Expression => String::new(),
+        // GitHub doesn't render DOT, so at least keep it readable
+        RenderFormat::Dot => format!(
+            "\n```dot\n{}\n```\n",
+            render::render(conditional, format, approvals)
+        ),
+        format => format!("\n{}\n", render::render(conditional, format, approvals)),
+    }
+}
+
+/// Points out reviewers in the minimum set that cover several team
+/// requirements on their own, by being on all of those teams. Returns an empty
+/// string if nobody does.
+pub fn display_team_coverage(
+    conditional: &OwnersConditional,
+    minimum: &MinimumOwnerSets,
+    members: &TeamMembers,
+) -> String {
+    let Some(set) = minimum.sets.first() else {
+        return String::new();
+    };
+    let team_clauses = conditional
+        .conjuncts()
+        .iter()
+        .filter(|clause| !clause.teams().is_empty())
+        .collect::<Vec<_>>();
+
+    set.iter()
+        .filter_map(|owner| {
+            let approvals = Approvals {
+                users: [owner.clone()].into(),
+                ..Approvals::default()
+            }
+            .with_team_members(members);
+            let covered = team_clauses
+                .iter()
+                .filter(|clause| clause.evaluate(&approvals) == Evaluation::Satisfied)
+                .count();
+            match covered {
+                0 | 1 => None,
+                covered if covered == team_clauses.len() => Some(format!(
+                    "- `{owner}` alone satisfies all {covered} team requirements\n"
+                )),
+                covered => Some(format!(
+                    "- `{owner}` alone satisfies {covered} of the {} team requirements\n",
+                    team_clauses.len()
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Renders a collapsible list of alternative reviewer sets. Returns an empty
+/// string if there's only one option, since it's already shown as the minimum
+/// set.
+pub fn display_alternative_owner_sets(
+    alternatives: &[WeightedOwnerSet],
+    show_cost: bool,
+) -> String {
+    if alternatives.len() <= 1 {
+        return String::new();
+    }
+
+    let items = alternatives
+        .iter()
+        .map(|alternative| {
+            let owners = alternative
+                .owners
+                .keys()
+                .map(|owner| format!("<code>{owner}</code>"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            if show_cost {
+                format!("<li>{owners} (cost {})</li>", alternative.cost)
+            } else {
+                format!("<li>{owners}</li>")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("");
+    format!(
+        r#"<details>
+    <summary>Alternatives</summary>
+    <ol>{items}</ol>
+</details>
+"#
+    )
+}
+
+/// Explains which files and CODEOWNERS rules each clause of the requirement
+/// comes from.
+pub fn display_provenance(tracked: &TrackedConditional) -> String {
+    tracked
+        .clauses
+        .iter()
+        .map(|clause| format!("- {}", describe_clause(clause)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Warns about files that only the PR author owns, since nobody else can
+/// approve them. Returns an empty string if there are none.
+pub fn display_no_eligible_owner(tracked: &TrackedConditional, author: &Owner) -> String {
+    if tracked.no_eligible_owner.is_empty() {
+        return String::new();
+    }
+
+    let clause_lines = tracked
+        .no_eligible_owner
+        .iter()
+        .map(|clause| format!("> - {}", describe_clause(clause)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        r#"> [!WARNING]
+> **No eligible owner**: these requirements can only be satisfied by the PR
+> author `{author}`, who can't approve their own PR. Another owner needs to be
+> added to CODEOWNERS, or an admin needs to merge this.
+{clause_lines}
+
+"#
+    )
+}
+
+/// Lists the reviews requested by optional CODEOWNERS sections, which don't
+/// block merging. Returns an empty string if there are none.
+pub fn display_optional_reviews(tracked: &TrackedConditional, approvals: &Approvals) -> String {
+    if tracked.advisory.is_empty() {
+        return String::new();
+    }
+
+    let clause_lines = tracked
+        .advisory
+        .iter()
+        .map(|clause| {
+            let state = match clause.conditional.evaluate(approvals) {
+                Evaluation::Satisfied => "✅",
+                Evaluation::Outstanding(_) => "⏳",
+            };
+            format!("- {state} {}", describe_clause(clause))
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "## Optional reviews\nThese owners are in optional CODEOWNERS sections, so their reviews are welcome but not required:\n{clause_lines}\n\n"
+    )
+}
+
+/// Summarises how the PR's edits to CODEOWNERS change who owns each file in the
+/// repo. Returns an empty string if the PR doesn't edit CODEOWNERS.
+pub fn display_codeowners_changes(diff: Option<&OwnershipDiff>) -> String {
+    let Some(diff) = diff else {
+        return String::new();
+    };
+    if diff.is_empty() {
+        return "## CODEOWNERS changes\nThis PR edits CODEOWNERS, but doesn't change the owners of any file.\n\n".to_string();
+    }
+
+    let owners = |change_owners: &BTreeSet<Owner>| {
+        change_owners
+            .iter()
+            .map(|owner| format!("`{owner}`"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let describe = |change: &OwnershipChange| {
+        if change.before.is_empty() {
+            format!(
+                "- `{}` gains owners: {}",
+                change.path,
+                owners(&change.after)
+            )
+        } else if change.after.is_empty() {
+            format!(
+                "- `{}` loses its owners: {}",
+                change.path,
+                owners(&change.before)
+            )
+        } else {
+            format!(
+                "- `{}` changes owners from {} to {}",
+                change.path,
+                owners(&change.before),
+                owners(&change.after)
+            )
+        }
+    };
+    let mut change_lines = diff
+        .gained
+        .iter()
+        .chain(&diff.lost)
+        .chain(&diff.changed)
+        .take(MAX_OWNERSHIP_CHANGES)
+        .map(describe)
+        .collect::<Vec<_>>();
+    if diff.len() > MAX_OWNERSHIP_CHANGES {
+        change_lines.push(format!(
+            "- and {} more files",
+            diff.len() - MAX_OWNERSHIP_CHANGES
+        ));
+    }
+    let plural = if diff.len() == 1 { "" } else { "s" };
+    format!(
+        "## CODEOWNERS changes\nThis PR edits CODEOWNERS, which changes the owners of {} file{plural} once it's merged:\n{}\n\n",
+        diff.len(),
+        change_lines.join("\n")
+    )
+}
+
+/// Notes how the requirement changed since the bot last commented. Returns an
+/// empty string if it didn't.
+pub fn display_requirement_changes(diff: &ConditionalDiff) -> String {
+    if diff.is_empty() {
+        return String::new();
+    }
+
+    let change_lines = diff
+        .added
+        .iter()
+        .map(|clause| format!("> - Now required: `{clause}`"))
+        .chain(
+            diff.removed
+                .iter()
+                .map(|clause| format!("> - No longer required: `{clause}`")),
+        )
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        r#"> [!NOTE]
+> **Requirements changed** since the last update:
+{change_lines}
+
+"#
+    )
+}
+
+/// Lists the changed files that have no owners. With `UnownedFiles::Block`
+/// this is a warning that they need owners first. Returns an empty string if
+/// every file has an owner.
+pub fn display_unowned_files(tracked: &TrackedConditional, policy: &UnownedFiles) -> String {
+    if tracked.unowned_files.is_empty() {
+        return String::new();
+    }
+
+    let file_lines = tracked
+        .unowned_files
+        .iter()
+        .map(|file| format!("- `{file}`"))
+        .collect::<Vec<_>>();
+    match policy {
+        UnownedFiles::Allow => format!(
+            "## Unowned files\nNo CODEOWNERS rule gives these files an owner, so they don't need anyone's review:\n{}\n\n",
+            file_lines.join("\n")
+        ),
+        UnownedFiles::Fallback(owners) => format!(
+            "## Unowned files\nNo CODEOWNERS rule gives these files an owner, so they need a review from one of the fallback owners ({}):\n{}\n\n",
+            owners
+                .iter()
+                .map(|owner| format!("`{owner}`"))
+                .collect::<Vec<_>>()
+                .join(", "),
+            file_lines.join("\n")
+        ),
+        UnownedFiles::Block => format!(
+            "> [!CAUTION]\n> **Unowned files**: no CODEOWNERS rule gives these files an owner. They need to be added to CODEOWNERS before this PR is merged.\n{}\n\n",
+            file_lines
+                .iter()
+                .map(|line| format!("> {line}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ),
+    }
+}
+
+/// Describes a clause along with the files and CODEOWNERS rules it comes from.
+fn describe_clause(clause: &TrackedClause) -> String {
+    let files = &clause.provenance.files;
+    let needed_for = if files.len() == 1 {
+        format!("`{}`", files.first().expect("exactly one file"))
+    } else {
+        format!("{} files", files.len())
+    };
+    // Only the fallback owners for unowned files have no rules
+    if clause.provenance.rules.is_empty() {
+        return format!(
+            "`{}` — fallback owners, needed for {needed_for} with no owners in CODEOWNERS",
+            clause.conditional
+        );
+    }
+    let rules = clause
+        .provenance
+        .rules
+        .iter()
+        .map(|rule| match &rule.section {
+            Some(section) => format!(
+                "`{}` in section `{section}` (CODEOWNERS line {})",
+                rule.pattern, rule.line
+            ),
+            None => format!("`{}` (CODEOWNERS line {})", rule.pattern, rule.line),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "`{}` — needed for {needed_for} under {rules}",
+        clause.conditional
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{blocked_files, display_approval_status};
+    use crate::{
+        conditional::Approvals, config::UnownedFiles, provenance::TrackedConditional,
+        rules::CodeownersRules,
+    };
+
+    #[test]
+    fn test_approval_status() {
+        let codeowners = CodeownersRules::parse("/src/ @a\n");
+        let status = |files: &[&str], policy: &UnownedFiles| {
+            let tracked = TrackedConditional::from_codeowners(&codeowners, files).reduce();
+            display_approval_status(
+                &tracked.conditional(),
+                &Approvals::from_logins(["a".to_string()]),
+                &blocked_files(&tracked, policy),
+            )
+        };
+
+        assert_eq!(
+            status(&["src/lib.rs", "notes.txt"], &UnownedFiles::Allow),
+            "All ownership requirements are satisfied\n- ✅ `@a`"
+        );
+        // No approval can make up for files that need an owner first
+        assert_eq!(
+            status(&["notes.txt"], &UnownedFiles::Block),
+            "Blocked: 1 unowned file(s) need an owner in CODEOWNERS before this PR is merged\n"
+        );
+        assert_eq!(
+            status(&["src/lib.rs", "notes.txt"], &UnownedFiles::Block),
+            "Blocked: 1 unowned file(s) need an owner in CODEOWNERS before this PR is merged\n- ✅ `@a`"
+        );
+        assert_eq!(
+            status(&["src/lib.rs"], &UnownedFiles::Block),
+            "All ownership requirements are satisfied\n- ✅ `@a`"
+        );
+    }
+}
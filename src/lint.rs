@@ -0,0 +1,240 @@
+//! Checks CODEOWNERS files for common mistakes: lines GitHub can't parse,
+//! rules that never apply because later rules always win, patterns that don't
+//! match anything and owners that don't exist.
+use crate::{codeowners::DiagnosticKind, owner::Owner, rules::CodeownersRules};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::{self, Display},
+};
+
+/// A problem on a single line of a CODEOWNERS file.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Finding {
+    /// 1-indexed line of the CODEOWNERS file.
+    pub line: usize,
+    pub problem: Problem,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Problem {
+    /// The line can't be parsed, or uses syntax GitHub doesn't support, so
+    /// GitHub skips it.
+    Syntax(DiagnosticKind),
+    /// Later rules, on these lines, take precedence for every file this rule
+    /// matches, so it never applies.
+    Shadowed(BTreeSet<usize>),
+    /// The pattern doesn't match any file in the tree.
+    NoMatches(String),
+    /// The owner doesn't exist, or can't be seen by the app.
+    UnknownOwner(Owner),
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.problem)
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Syntax(kind) => write!(f, "{kind}, so the line is skipped"),
+            Problem::Shadowed(by) => {
+                let lines = by
+                    .iter()
+                    .map(|line| line.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let plural = if by.len() == 1 { "" } else { "s" };
+                write!(
+                    f,
+                    "rule never applies, it's overridden by line{plural} {lines}"
+                )
+            }
+            Problem::NoMatches(pattern) => write!(f, "`{pattern}` doesn't match any file"),
+            Problem::UnknownOwner(owner) => write!(f, "owner `{owner}` doesn't exist"),
+        }
+    }
+}
+
+/// Lints a parsed CODEOWNERS file. If given the `files` in the repo, also
+/// reports rules that don't match any of them, or that only match files later
+/// rules take over. Findings are sorted by line.
+pub fn lint(codeowners: &CodeownersRules, files: Option<&[&str]>) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = codeowners
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| Finding {
+            line: diagnostic.span.line,
+            problem: Problem::Syntax(diagnostic.kind.clone()),
+        })
+        .collect();
+
+    let rules = codeowners.rules();
+    // The line deciding the owners of each file within each section. Rules
+    // in different sections never override each other
+    let winners: Option<HashMap<(&str, Option<usize>), usize>> = files.map(|files| {
+        files
+            .iter()
+            .flat_map(|file| {
+                codeowners
+                    .matching_rules(file)
+                    .into_iter()
+                    .map(|rule| ((*file, rule.section), rule.line))
+            })
+            .collect()
+    });
+    for (idx, rule) in rules.iter().enumerate() {
+        let later = &rules[idx + 1..];
+        // Without a tree we can still tell when a later rule matches
+        // everything this one does
+        let shadowed_by: BTreeSet<usize> = later
+            .iter()
+            .filter(|other| other.section == rule.section)
+            .filter(|other| other.pattern == rule.pattern || is_catch_all(&other.pattern))
+            .map(|other| other.line)
+            .take(1)
+            .collect();
+        if !shadowed_by.is_empty() {
+            findings.push(Finding {
+                line: rule.line,
+                problem: Problem::Shadowed(shadowed_by),
+            });
+            continue;
+        }
+
+        let (Some(files), Some(winners)) = (files, &winners) else {
+            continue;
+        };
+        let matched: Vec<&str> = files
+            .iter()
+            .copied()
+            .filter(|file| rule.matches(file))
+            .collect();
+        let won_by: BTreeSet<usize> = matched
+            .iter()
+            .map(|file| winners[&(*file, rule.section)])
+            .collect();
+        if matched.is_empty() {
+            findings.push(Finding {
+                line: rule.line,
+                problem: Problem::NoMatches(rule.pattern.clone()),
+            });
+        } else if !won_by.contains(&rule.line) {
+            findings.push(Finding {
+                line: rule.line,
+                problem: Problem::Shadowed(won_by),
+            });
+        }
+    }
+
+    findings.sort();
+    findings
+}
+
+/// Reports every rule that lists one of the `missing` owners, once per owner
+/// even if the rule lists it more than once.
+pub fn unknown_owners(codeowners: &CodeownersRules, missing: &BTreeSet<Owner>) -> Vec<Finding> {
+    codeowners
+        .rules()
+        .iter()
+        .flat_map(|rule| {
+            rule.owners
+                .iter()
+                .filter(|owner| missing.contains(owner))
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .map(|owner| Finding {
+                    line: rule.line,
+                    problem: Problem::UnknownOwner(owner.clone()),
+                })
+        })
+        .collect()
+}
+
+fn is_catch_all(pattern: &str) -> bool {
+    matches!(pattern, "*" | "**" | "/**")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Finding, Problem, lint, unknown_owners};
+    use crate::{codeowners::DiagnosticKind, rules::CodeownersRules};
+
+    fn finding(line: usize, problem: Problem) -> Finding {
+        Finding { line, problem }
+    }
+
+    #[test]
+    fn test_lint() {
+        let codeowners = CodeownersRules::parse(
+            "# Owners\n\
+             docs/ @docs\n\
+             *.rs @rust # trailing comment\n\
+             !*.md @a\n\
+             /src/[ab].rs @a\n\
+             /src/ @src\n\
+             /src/lib.rs @lib\n\
+             /old/ @gone\n\
+             docs/ @writers\n\
+             /lib/ @lib not-an-owner\n",
+        );
+
+        assert_eq!(
+            lint(&codeowners, None),
+            vec![
+                finding(2, Problem::Shadowed([9].into())),
+                finding(4, Problem::Syntax(DiagnosticKind::Negation)),
+                finding(5, Problem::Syntax(DiagnosticKind::CharacterRange)),
+                finding(
+                    10,
+                    Problem::Syntax(DiagnosticKind::InvalidOwner("not-an-owner".into()))
+                ),
+            ]
+        );
+
+        let files = ["docs/index.md", "src/lib.rs", "src/main.rs", "README.md"];
+        assert_eq!(
+            lint(&codeowners, Some(&files)),
+            vec![
+                finding(2, Problem::Shadowed([9].into())),
+                finding(3, Problem::Shadowed([6, 7].into())),
+                finding(4, Problem::Syntax(DiagnosticKind::Negation)),
+                finding(5, Problem::Syntax(DiagnosticKind::CharacterRange)),
+                finding(8, Problem::NoMatches("/old/".into())),
+                finding(
+                    10,
+                    Problem::Syntax(DiagnosticKind::InvalidOwner("not-an-owner".into()))
+                ),
+            ]
+        );
+        assert_eq!(
+            finding(3, Problem::Shadowed([6, 7].into())).to_string(),
+            "line 3: rule never applies, it's overridden by lines 6, 7"
+        );
+        assert_eq!(
+            finding(4, Problem::Syntax(DiagnosticKind::Negation)).to_string(),
+            "line 4: `!` negation is not supported, so the line is skipped"
+        );
+        assert_eq!(
+            finding(8, Problem::NoMatches("/old/".into())).to_string(),
+            "line 8: `/old/` doesn't match any file"
+        );
+
+        assert_eq!(
+            lint(&CodeownersRules::parse("/src/ @a\n* @b\n"), None),
+            vec![finding(1, Problem::Shadowed([2].into()))]
+        );
+
+        // Sections don't override each other
+        let codeowners = CodeownersRules::parse("* @a\n[Docs]\n*.md @docs\n[Other]\n* @b\n");
+        assert_eq!(lint(&codeowners, None), vec![]);
+        assert_eq!(lint(&codeowners, Some(&["README.md", "a.rs"])), vec![]);
+
+        let codeowners = CodeownersRules::parse("/src/ @src\n/old/ @gone @Gone\n");
+        assert_eq!(
+            unknown_owners(&codeowners, &["@GONE".into()].into()),
+            vec![finding(2, Problem::UnknownOwner("@gone".into()))]
+        );
+    }
+}
@@ -19,6 +19,7 @@ pub struct MetricsReporter {
     dd_client: DdClient,
     loop_duration_timer: Histogram<f64>,
     loop_load_hist: Histogram<f64>,
+    unowned_files_hist: Histogram<f64>,
 }
 
 impl MetricsReporter {
@@ -34,10 +35,12 @@ impl MetricsReporter {
         // Create a meter from the above MeterProvider.
         let loop_duration_timer = meter.f64_histogram("loop_duration_ms").init();
         let loop_load_hist = meter.f64_histogram("loop_load").init();
+        let unowned_files_hist = meter.f64_histogram("unowned_files").init();
         Ok(MetricsReporter {
             dd_client,
             loop_duration_timer,
             loop_load_hist,
+            unowned_files_hist,
         })
     }
 
@@ -64,6 +67,19 @@ impl MetricsReporter {
         mr.loop_load_hist.record(loop_load, &[]);
     }
 
+    /// Reports how many of the files changed by a PR have no owners.
+    pub fn report_unowned_files(unowned_files: usize) {
+        let lg = DD_CLIENT.read().unwrap();
+        let mr = match lg.as_ref() {
+            Some(c) => c,
+            None => {
+                return;
+            }
+        };
+        mr.dd_client.gauge("unowned_files", unowned_files as f64, &None);
+        mr.unowned_files_hist.record(unowned_files as f64, &[]);
+    }
+
     fn init_meter() -> Result<Meter> {
         Ok(opentelemetry_otlp::new_pipeline()
             .metrics(opentelemetry::sdk::runtime::Tokio)
@@ -0,0 +1,239 @@
+//! Typed owners, as they show up in CODEOWNERS files.
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display},
+    hash::{Hash, Hasher},
+};
+
+/// A user, team or email that can own files. Comparisons ignore ASCII case,
+/// like GitHub does, but the original spelling is kept for display.
+///
+/// Serializes as the same string it's written as in CODEOWNERS, e.g. `@user`,
+/// `@org/team` or `user@example.com`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "String", from = "String")]
+pub enum Owner {
+    /// A GitHub login, without the leading `@`.
+    User(String),
+    Team {
+        org: String,
+        team: String,
+    },
+    Email(String),
+}
+
+impl Owner {
+    pub fn is_team(&self) -> bool {
+        matches!(self, Owner::Team { .. })
+    }
+
+    /// Bytes of the displayed form, lowercased, used for all comparisons.
+    /// Owners are compared a lot while searching for reviewers, so this
+    /// avoids building a string for every comparison.
+    fn key(&self) -> impl Iterator<Item = u8> + '_ {
+        let (prefix, first, separator, second) = match self {
+            Owner::User(login) => ("@", login.as_str(), "", ""),
+            Owner::Team { org, team } => ("@", org.as_str(), "/", team.as_str()),
+            Owner::Email(email) => ("", email.as_str(), "", ""),
+        };
+        [prefix, first, separator, second]
+            .into_iter()
+            .flat_map(str::bytes)
+            .map(|byte| byte.to_ascii_lowercase())
+    }
+}
+
+/// Classifies an owner the same way GitHub reads CODEOWNERS: `@org/team` is a
+/// team, `@login` a user and anything else containing an `@` an email. Bare
+/// names without an `@` are taken to be user logins.
+impl From<&str> for Owner {
+    fn from(name: &str) -> Owner {
+        match name.strip_prefix('@') {
+            Some(handle) => match handle.split_once('/') {
+                Some((org, team)) => Owner::Team {
+                    org: org.to_string(),
+                    team: team.to_string(),
+                },
+                None => Owner::User(handle.to_string()),
+            },
+            None if name.contains('@') => Owner::Email(name.to_string()),
+            None => Owner::User(name.to_string()),
+        }
+    }
+}
+
+impl From<String> for Owner {
+    fn from(name: String) -> Owner {
+        Owner::from(name.as_str())
+    }
+}
+
+impl From<Owner> for String {
+    fn from(owner: Owner) -> String {
+        owner.to_string()
+    }
+}
+
+impl Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Owner::User(login) => write!(f, "@{login}"),
+            Owner::Team { org, team } => write!(f, "@{org}/{team}"),
+            Owner::Email(email) => write!(f, "{email}"),
+        }
+    }
+}
+
+impl PartialEq for Owner {
+    fn eq(&self, other: &Self) -> bool {
+        self.key().eq(other.key())
+    }
+}
+
+impl Eq for Owner {}
+
+impl Hash for Owner {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Written in fixed size chunks, so equal owners hash the same however
+        // their names are split up
+        let mut buffer = [0; 64];
+        let mut len = 0;
+        for byte in self.key() {
+            if len == buffer.len() {
+                state.write(&buffer);
+                len = 0;
+            }
+            buffer[len] = byte;
+            len += 1;
+        }
+        state.write(&buffer[..len]);
+        state.write_u8(0xff);
+    }
+}
+
+impl Ord for Owner {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(other.key())
+    }
+}
+
+impl PartialOrd for Owner {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Known members of each team, used to expand teams into the individuals on
+/// them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TeamMembers {
+    teams: BTreeMap<Owner, BTreeSet<Owner>>,
+}
+
+impl TeamMembers {
+    pub fn insert(&mut self, team: Owner, members: BTreeSet<Owner>) {
+        self.teams.insert(team, members);
+    }
+
+    pub fn members_of(&self, team: &Owner) -> Option<&BTreeSet<Owner>> {
+        self.teams.get(team)
+    }
+
+    /// Every known team `user` is a member of.
+    pub fn teams_of(&self, user: &Owner) -> BTreeSet<Owner> {
+        self.teams
+            .iter()
+            .filter(|(_, members)| members.contains(user))
+            .map(|(team, _)| team.clone())
+            .collect()
+    }
+}
+
+impl FromIterator<(Owner, BTreeSet<Owner>)> for TeamMembers {
+    fn from_iter<I: IntoIterator<Item = (Owner, BTreeSet<Owner>)>>(iter: I) -> TeamMembers {
+        TeamMembers {
+            teams: iter.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Owner, TeamMembers};
+
+    #[test]
+    fn test_owner_kinds() -> anyhow::Result<()> {
+        assert_eq!(Owner::from("@alice"), Owner::User("alice".into()));
+        assert_eq!(Owner::from("alice"), Owner::User("alice".into()));
+        assert_eq!(
+            Owner::from("@org/backend"),
+            Owner::Team {
+                org: "org".into(),
+                team: "backend".into()
+            }
+        );
+        assert_eq!(
+            Owner::from("alice@example.com"),
+            Owner::Email("alice@example.com".into())
+        );
+        assert!(Owner::from("@org/backend").is_team());
+        assert!(!Owner::from("@alice").is_team());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_owner_case_insensitive() -> anyhow::Result<()> {
+        assert_eq!(Owner::from("@Alice"), Owner::from("@alice"));
+        assert_eq!(Owner::from("@Org/Backend"), Owner::from("@org/backend"));
+        assert_ne!(Owner::from("@org/alice"), Owner::from("@alice"));
+        assert_eq!(format!("{}", Owner::from("@Org/Backend")), "@Org/Backend");
+
+        let owners: std::collections::HashSet<Owner> =
+            [Owner::from("@Alice"), Owner::from("@ALICE")].into();
+        assert_eq!(owners.len(), 1);
+        // Longer than the chunks names are hashed in
+        let long = format!("@org/{}", "Team".repeat(40));
+        let owners: std::collections::HashSet<Owner> =
+            [Owner::from(long.as_str()), Owner::from(long.to_lowercase())].into();
+        assert_eq!(owners.len(), 1);
+        assert!(Owner::from("@Org/B") < Owner::from("@org/c"));
+        assert!(Owner::from("@org/team") < Owner::from("@orgs"));
+        assert_eq!(
+            serde_json::to_string(&Owner::from("@org/backend"))?,
+            "\"@org/backend\""
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_team_members() -> anyhow::Result<()> {
+        let members: TeamMembers = [
+            (
+                Owner::from("@org/backend"),
+                [Owner::from("@alice"), Owner::from("@Bob")].into(),
+            ),
+            (Owner::from("@org/infra"), [Owner::from("@bob")].into()),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            members.teams_of(&Owner::from("@bob")),
+            [Owner::from("@org/backend"), Owner::from("@org/infra")].into()
+        );
+        assert!(members.teams_of(&Owner::from("@carol")).is_empty());
+        assert_eq!(
+            members
+                .members_of(&Owner::from("@Org/Infra"))
+                .map(|members| members.len()),
+            Some(1)
+        );
+        assert!(members.members_of(&Owner::from("@org/web")).is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+//! Compact sets of owners for the solvers. Every distinct owner gets a small
+//! integer id, so a set of owners is a bitset and checking whether two sets
+//! overlap takes a few word operations instead of string comparisons.
+use crate::owner::Owner;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+};
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// Maps owners to ids and back. Ids are handed out in the order of the owners,
+/// so `OwnerSet`s iterate and compare in the same order a `BTreeSet<Owner>`
+/// would.
+#[derive(Debug, Clone, Default)]
+pub struct OwnerInterner {
+    owners: Vec<Owner>,
+    ids: HashMap<Owner, usize>,
+}
+
+impl OwnerInterner {
+    pub fn new<'a, I>(owners: I) -> OwnerInterner
+    where
+        I: IntoIterator<Item = &'a Owner>,
+    {
+        let owners: Vec<Owner> = owners
+            .into_iter()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .cloned()
+            .collect();
+        let ids = owners
+            .iter()
+            .enumerate()
+            .map(|(id, owner)| (owner.clone(), id))
+            .collect();
+        OwnerInterner { owners, ids }
+    }
+
+    pub fn len(&self) -> usize {
+        self.owners.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.owners.is_empty()
+    }
+
+    pub fn id(&self, owner: &Owner) -> Option<usize> {
+        self.ids.get(owner).copied()
+    }
+
+    pub fn owner(&self, id: usize) -> &Owner {
+        &self.owners[id]
+    }
+
+    /// Panics if any of `owners` wasn't interned.
+    pub fn set_of<'a, I>(&self, owners: I) -> OwnerSet
+    where
+        I: IntoIterator<Item = &'a Owner>,
+    {
+        owners
+            .into_iter()
+            .map(|owner| self.id(owner).expect("owner was not interned"))
+            .collect()
+    }
+
+    pub fn owners_of(&self, set: &OwnerSet) -> BTreeSet<Owner> {
+        set.iter().map(|id| self.owner(id).clone()).collect()
+    }
+}
+
+/// A set of owner ids, stored as a bitset.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct OwnerSet {
+    // Never has trailing zero words, so equal sets have equal words
+    words: Vec<u64>,
+}
+
+impl OwnerSet {
+    pub fn insert(&mut self, id: usize) {
+        let word = id / WORD_BITS;
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << (id % WORD_BITS);
+    }
+
+    pub fn remove(&mut self, id: usize) {
+        if let Some(word) = self.words.get_mut(id / WORD_BITS) {
+            *word &= !(1 << (id % WORD_BITS));
+        }
+        while self.words.last() == Some(&0) {
+            self.words.pop();
+        }
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        self.words
+            .get(id / WORD_BITS)
+            .is_some_and(|word| word & (1 << (id % WORD_BITS)) != 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn is_subset(&self, other: &OwnerSet) -> bool {
+        self.words.len() <= other.words.len()
+            && self
+                .words
+                .iter()
+                .zip(&other.words)
+                .all(|(word, other_word)| word & !other_word == 0)
+    }
+
+    pub fn is_disjoint(&self, other: &OwnerSet) -> bool {
+        self.words
+            .iter()
+            .zip(&other.words)
+            .all(|(word, other_word)| word & other_word == 0)
+    }
+
+    pub fn intersection_len(&self, other: &OwnerSet) -> usize {
+        self.words
+            .iter()
+            .zip(&other.words)
+            .map(|(word, other_word)| (word & other_word).count_ones() as usize)
+            .sum()
+    }
+
+    pub fn union(&self, other: &OwnerSet) -> OwnerSet {
+        let (longer, shorter) = if self.words.len() >= other.words.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let mut words = longer.words.clone();
+        for (word, other_word) in words.iter_mut().zip(&shorter.words) {
+            *word |= other_word;
+        }
+        OwnerSet { words }
+    }
+
+    /// The ids in the set, smallest first.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(idx, &word)| {
+            let mut remaining = word;
+            std::iter::from_fn(move || {
+                if remaining == 0 {
+                    return None;
+                }
+                let bit = remaining.trailing_zeros() as usize;
+                remaining &= remaining - 1;
+                Some(idx * WORD_BITS + bit)
+            })
+        })
+    }
+
+    /// The smallest id in the set.
+    pub fn first(&self) -> Option<usize> {
+        self.iter().next()
+    }
+}
+
+impl FromIterator<usize> for OwnerSet {
+    fn from_iter<I: IntoIterator<Item = usize>>(ids: I) -> OwnerSet {
+        let mut set = OwnerSet::default();
+        for id in ids {
+            set.insert(id);
+        }
+        set
+    }
+}
+
+/// Compares the ids in order, like a `BTreeSet<usize>` does.
+impl Ord for OwnerSet {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl PartialOrd for OwnerSet {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{OwnerInterner, OwnerSet};
+    use crate::owner::Owner;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_owner_set() {
+        let owners: Vec<Owner> = ["@b", "@a", "@org/c", "@A"]
+            .into_iter()
+            .map(Owner::from)
+            .collect();
+        let interner = OwnerInterner::new(&owners);
+        assert_eq!(interner.len(), 3);
+        assert_eq!(interner.id(&"@a".into()), Some(0));
+        assert_eq!(interner.id(&"@org/c".into()), Some(2));
+        assert_eq!(interner.id(&"@d".into()), None);
+
+        let set = interner.set_of(&owners[..2]);
+        assert_eq!(
+            interner.owners_of(&set),
+            BTreeSet::from(["@a".into(), "@b".into()])
+        );
+
+        let small: OwnerSet = [3, 70].into_iter().collect();
+        let large: OwnerSet = [1, 3, 70, 200].into_iter().collect();
+        assert_eq!(large.iter().collect::<Vec<_>>(), vec![1, 3, 70, 200]);
+        assert_eq!(large.len(), 4);
+        assert!(small.is_subset(&large));
+        assert!(!large.is_subset(&small));
+        assert!(small.is_disjoint(&[1, 200].into_iter().collect()));
+        assert_eq!(small.intersection_len(&large), 2);
+        assert_eq!(small.union(&[1].into_iter().collect()).first(), Some(1));
+        assert!(large < small);
+
+        let mut set = large.clone();
+        set.remove(200);
+        set.remove(1);
+        assert_eq!(set, small);
+        assert!(!set.contains(200));
+        set.remove(3);
+        set.remove(70);
+        assert!(set.is_empty());
+        assert_eq!(set, OwnerSet::default());
+    }
+}
@@ -0,0 +1,113 @@
+//! How a change to CODEOWNERS changes who owns the files in the repo.
+use crate::{owner::Owner, rules::CodeownersRules};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// The owners of a file before and after a change to CODEOWNERS.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnershipChange {
+    pub path: String,
+    pub before: BTreeSet<Owner>,
+    pub after: BTreeSet<Owner>,
+}
+
+/// Files whose owners differ between two versions of CODEOWNERS, sorted by
+/// path. Owners are compared across all sections, ignoring their order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnershipDiff {
+    /// Files that had no owners before.
+    pub gained: Vec<OwnershipChange>,
+    /// Files that have no owners after.
+    pub lost: Vec<OwnershipChange>,
+    /// Files that have different owners.
+    pub changed: Vec<OwnershipChange>,
+}
+
+impl OwnershipDiff {
+    pub fn new(before: &CodeownersRules, after: &CodeownersRules, files: &[&str]) -> OwnershipDiff {
+        let files: BTreeSet<&str> = files.iter().copied().collect();
+        let mut diff = OwnershipDiff::default();
+        for file in files {
+            let change = OwnershipChange {
+                path: file.to_string(),
+                before: owners_of(before, file),
+                after: owners_of(after, file),
+            };
+            if change.before == change.after {
+                continue;
+            }
+            if change.before.is_empty() {
+                diff.gained.push(change);
+            } else if change.after.is_empty() {
+                diff.lost.push(change);
+            } else {
+                diff.changed.push(change);
+            }
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How many files have different owners.
+    pub fn len(&self) -> usize {
+        self.gained.len() + self.lost.len() + self.changed.len()
+    }
+}
+
+fn owners_of(codeowners: &CodeownersRules, path: &str) -> BTreeSet<Owner> {
+    codeowners
+        .owners_of(path)
+        .unwrap_or_default()
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{OwnershipChange, OwnershipDiff};
+    use crate::{owner::Owner, rules::CodeownersRules};
+
+    fn change(path: &str, before: &[&str], after: &[&str]) -> OwnershipChange {
+        OwnershipChange {
+            path: path.into(),
+            before: before.iter().map(|owner| Owner::from(*owner)).collect(),
+            after: after.iter().map(|owner| Owner::from(*owner)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_ownership_diff() {
+        let before = CodeownersRules::parse("/src/ @a @b\n/docs/ @docs\n/old/ @old\n");
+        let after =
+            CodeownersRules::parse("/src/ @b @a\n/src/api/ @api\n/docs/ @docs\n/new/ @new\n");
+        let files = [
+            "README.md",
+            "src/lib.rs",
+            "src/api/mod.rs",
+            "docs/index.md",
+            "old/a.rs",
+            "new/b.rs",
+        ];
+
+        let diff = OwnershipDiff::new(&before, &after, &files);
+        assert_eq!(
+            diff,
+            OwnershipDiff {
+                gained: vec![change("new/b.rs", &[], &["@new"])],
+                lost: vec![change("old/a.rs", &["@old"], &[])],
+                // Reordering owners doesn't change anything
+                changed: vec![change("src/api/mod.rs", &["@a", "@b"], &["@api"])],
+            }
+        );
+        assert_eq!(diff.len(), 3);
+
+        assert!(OwnershipDiff::new(&before, &before, &files).is_empty());
+        assert_eq!(
+            OwnershipDiff::new(&CodeownersRules::default(), &before, &["src/lib.rs"]).gained,
+            vec![change("src/lib.rs", &[], &["@a", "@b"])]
+        );
+    }
+}
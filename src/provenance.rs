@@ -0,0 +1,463 @@
+//! Keeps track of which files and CODEOWNERS rules each clause of a PR's
+//! requirement comes from, so the comment can explain why each reviewer is
+//! needed.
+use crate::{
+    conditional::OwnersConditional, owner::Owner, owner_set::OwnerSet, rules::CodeownersRules,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// A CODEOWNERS rule, as shown to users.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RuleRef {
+    pub line: usize,
+    pub pattern: String,
+    /// Name of the section the rule is in, if the file has sections.
+    pub section: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    pub files: BTreeSet<String>,
+    pub rules: BTreeSet<RuleRef>,
+}
+
+impl Provenance {
+    fn merge(&mut self, other: Provenance) {
+        self.files.extend(other.files);
+        self.rules.extend(other.rules);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackedClause {
+    pub conditional: OwnersConditional,
+    pub provenance: Provenance,
+}
+
+/// The same requirement as `OwnersConditional::from_codeowners`, kept as a
+/// list of clauses that all need to be satisfied, each with its provenance.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackedConditional {
+    pub clauses: Vec<TrackedClause>,
+    /// Clauses nobody who is allowed to can approve, as they were before
+    /// removing the PR author. These are left out of `clauses`.
+    pub no_eligible_owner: Vec<TrackedClause>,
+    /// Files that no CODEOWNERS rule gives an owner to.
+    pub unowned_files: BTreeSet<String>,
+    /// Clauses from optional CODEOWNERS sections. Reviews from these owners
+    /// are welcome but not required, so they're left out of `clauses`.
+    pub advisory: Vec<TrackedClause>,
+}
+
+impl TrackedConditional {
+    pub fn from_codeowners(codeowners: &CodeownersRules, files: &[&str]) -> TrackedConditional {
+        let mut clauses = vec![];
+        let mut advisory = vec![];
+        let mut unowned_files = BTreeSet::new();
+        for file in files {
+            let mut owned = false;
+            for requirement in codeowners.requirements_of(file) {
+                // A rule with no owners explicitly leaves files unowned
+                let Some(conditional) = OwnersConditional::from_owners_at_least(
+                    &requirement.rule.owners,
+                    requirement.approvals,
+                ) else {
+                    continue;
+                };
+                owned = true;
+                let clause = TrackedClause {
+                    conditional,
+                    provenance: Provenance {
+                        files: [file.to_string()].into(),
+                        rules: [RuleRef {
+                            line: requirement.rule.line,
+                            pattern: requirement.rule.pattern.clone(),
+                            section: requirement.section.map(|section| section.name.clone()),
+                        }]
+                        .into(),
+                    },
+                };
+                if requirement.is_optional() {
+                    advisory.push(clause);
+                } else {
+                    clauses.push(clause);
+                }
+            }
+            if !owned {
+                unowned_files.insert(file.to_string());
+            }
+        }
+        TrackedConditional {
+            clauses,
+            no_eligible_owner: vec![],
+            unowned_files,
+            advisory,
+        }
+    }
+
+    /// Requires a review from any of `owners` for the unowned files. They are
+    /// still listed in `unowned_files`.
+    pub fn with_fallback_owners(mut self, owners: &[Owner]) -> TrackedConditional {
+        if !self.unowned_files.is_empty()
+            && let Some(conditional) = OwnersConditional::from_owners(owners)
+        {
+            self.clauses.push(TrackedClause {
+                conditional,
+                provenance: Provenance {
+                    files: self.unowned_files.clone(),
+                    rules: BTreeSet::new(),
+                },
+            });
+        }
+        self
+    }
+
+    /// Same as `OwnersConditional::remove_all`, dropping any clause that ends
+    /// up with no owners.
+    pub fn remove_all(self, excluded_owners: &HashSet<Owner>) -> TrackedConditional {
+        let clauses = self
+            .clauses
+            .into_iter()
+            .filter_map(|clause| {
+                Some(TrackedClause {
+                    conditional: clause.conditional.remove_all(excluded_owners)?,
+                    provenance: clause.provenance,
+                })
+            })
+            .collect();
+        let advisory = self
+            .advisory
+            .into_iter()
+            .filter_map(|clause| {
+                Some(TrackedClause {
+                    conditional: clause.conditional.remove_all(excluded_owners)?,
+                    provenance: clause.provenance,
+                })
+            })
+            .collect();
+        TrackedConditional {
+            clauses,
+            no_eligible_owner: self.no_eligible_owner,
+            unowned_files: self.unowned_files,
+            advisory,
+        }
+    }
+
+    /// Removes the PR author from every clause, since GitHub doesn't let
+    /// authors approve their own PRs. Clauses that nobody else can approve are
+    /// moved to `no_eligible_owner`.
+    pub fn remove_author(self, author: &Owner) -> TrackedConditional {
+        let mut clauses = vec![];
+        let mut no_eligible_owner = self.no_eligible_owner;
+        for clause in self.clauses {
+            let conditional = clause.conditional.clone().remove_owner(author);
+            if conditional.is_satisfiable() {
+                clauses.push(TrackedClause {
+                    conditional,
+                    provenance: clause.provenance,
+                });
+            } else {
+                no_eligible_owner.push(clause);
+            }
+        }
+        // Nobody needs to approve advisory clauses, so ones the author can't
+        // approve are simply dropped
+        let advisory = self
+            .advisory
+            .into_iter()
+            .filter_map(|clause| {
+                let conditional = clause.conditional.remove_owner(author);
+                conditional.is_satisfiable().then_some(TrackedClause {
+                    conditional,
+                    provenance: clause.provenance,
+                })
+            })
+            .collect();
+        TrackedConditional {
+            clauses,
+            no_eligible_owner,
+            unowned_files: self.unowned_files,
+            advisory,
+        }
+    }
+
+    /// Reduces every clause and merges duplicate clauses along with their
+    /// provenance. A clause absorbed by a stricter one (e.g. `(a || b)` next
+    /// to `a`) hands its provenance over to it, since approving the stricter
+    /// clause is what covers those files.
+    pub fn reduce(self) -> TrackedConditional {
+        let mut clauses: Vec<TrackedClause> = vec![];
+        let mut reduced: Vec<_> = self
+            .clauses
+            .into_iter()
+            .map(|clause| TrackedClause {
+                conditional: clause.conditional.reduce(),
+                provenance: clause.provenance,
+            })
+            .collect();
+        // Handle the strictest clauses first, so absorbed clauses always have
+        // somewhere to go
+        reduced.sort_by_key(|clause| clause.conditional.disjuncts().len());
+
+        // Any clause that absorbs another has one of its disjuncts first, so
+        // only those need comparing
+        let sets = disjunct_sets(&reduced);
+        let mut kept: Vec<OwnerSet> = vec![];
+        let mut positions: HashMap<OwnerSet, usize> = HashMap::new();
+        let mut by_first: HashMap<usize, Vec<usize>> = HashMap::new();
+        // Clauses with no disjuncts at all absorb everything else
+        let mut absorb_all = vec![];
+        for (clause, set) in reduced.into_iter().zip(sets) {
+            let absorbing = positions.get(&set).copied().or_else(|| {
+                set.iter()
+                    .filter_map(|id| by_first.get(&id))
+                    .flatten()
+                    .chain(&absorb_all)
+                    .copied()
+                    .filter(|&idx| kept[idx].is_subset(&set))
+                    .min()
+            });
+            match absorbing {
+                Some(idx) => clauses[idx].provenance.merge(clause.provenance),
+                None => {
+                    match set.first() {
+                        Some(first) => by_first.entry(first).or_default().push(kept.len()),
+                        None => absorb_all.push(kept.len()),
+                    }
+                    positions.insert(set.clone(), kept.len());
+                    kept.push(set);
+                    clauses.push(clause);
+                }
+            }
+        }
+        clauses.sort_by(|a, b| a.conditional.cmp(&b.conditional));
+
+        TrackedConditional {
+            clauses,
+            no_eligible_owner: merge_duplicates(self.no_eligible_owner),
+            unowned_files: self.unowned_files,
+            advisory: merge_duplicates(self.advisory),
+        }
+    }
+
+    /// The plain, reduced requirement.
+    pub fn conditional(&self) -> OwnersConditional {
+        OwnersConditional::And(
+            self.clauses
+                .iter()
+                .map(|clause| clause.conditional.clone())
+                .collect(),
+        )
+        .reduce()
+    }
+}
+
+/// Reduces each clause and merges the ones that end up the same.
+fn merge_duplicates(tracked: Vec<TrackedClause>) -> Vec<TrackedClause> {
+    let reduced: Vec<_> = tracked
+        .into_iter()
+        .map(|clause| TrackedClause {
+            conditional: clause.conditional.reduce(),
+            provenance: clause.provenance,
+        })
+        .collect();
+    // Reduced disjuncts are sorted and free of duplicates, so clauses with the
+    // same disjuncts are the same
+    let sets = disjunct_sets(&reduced);
+    let mut clauses: Vec<TrackedClause> = vec![];
+    let mut positions: HashMap<OwnerSet, usize> = HashMap::new();
+    for (clause, set) in reduced.into_iter().zip(sets) {
+        match positions.get(&set) {
+            Some(&idx) => clauses[idx].provenance.merge(clause.provenance),
+            None => {
+                positions.insert(set, clauses.len());
+                clauses.push(clause);
+            }
+        }
+    }
+    clauses.sort_by(|a, b| a.conditional.cmp(&b.conditional));
+    clauses
+}
+
+/// The disjuncts of each clause as a set of ids, one per distinct disjunct
+/// across all of `clauses`. Most disjuncts are single owners, but nested terms
+/// get an id too.
+fn disjunct_sets(clauses: &[TrackedClause]) -> Vec<OwnerSet> {
+    let mut ids: BTreeMap<&OwnersConditional, usize> = BTreeMap::new();
+    clauses
+        .iter()
+        .map(|clause| {
+            clause
+                .conditional
+                .disjuncts()
+                .iter()
+                .map(|term| {
+                    let next = ids.len();
+                    *ids.entry(term).or_insert(next)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Provenance, RuleRef, TrackedConditional};
+    use crate::{conditional::OwnersConditional, rules::CodeownersRules};
+
+    fn rule(line: usize, pattern: &str) -> RuleRef {
+        RuleRef {
+            line,
+            pattern: pattern.into(),
+            section: None,
+        }
+    }
+
+    #[test]
+    fn test_tracked_conditional() -> anyhow::Result<()> {
+        let codeowners = CodeownersRules::parse(
+            "# Owners\n*.md @docs @a\n\nbilling/ @a\nbilling/internal/ @a\nsrc/ @b @c\n",
+        );
+        let files = [
+            "README.md",
+            "billing/a.rs",
+            "billing/b.rs",
+            "billing/internal/c.rs",
+            "src/lib.rs",
+            "unowned.txt",
+        ];
+        let tracked = TrackedConditional::from_codeowners(&codeowners, &files).reduce();
+
+        assert_eq!(
+            tracked.conditional(),
+            OwnersConditional::from_codeowners(&codeowners, &files).reduce()
+        );
+        assert_eq!(
+            tracked
+                .clauses
+                .iter()
+                .map(|clause| (format!("{}", clause.conditional), clause.provenance.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "@a".to_string(),
+                    Provenance {
+                        files: [
+                            "README.md".to_string(),
+                            "billing/a.rs".into(),
+                            "billing/b.rs".into(),
+                            "billing/internal/c.rs".into(),
+                        ]
+                        .into(),
+                        rules: [
+                            rule(2, "*.md"),
+                            rule(4, "billing/"),
+                            rule(5, "billing/internal/")
+                        ]
+                        .into(),
+                    }
+                ),
+                (
+                    "(@b || @c)".to_string(),
+                    Provenance {
+                        files: ["src/lib.rs".to_string()].into(),
+                        rules: [rule(6, "src/")].into(),
+                    }
+                ),
+            ]
+        );
+
+        let removed = tracked
+            .clone()
+            .remove_all(&["@b".into(), "@c".into()].into());
+        assert_eq!(removed.clauses.len(), 1);
+        assert_eq!(removed.conditional(), OwnersConditional::Owner("@a".into()));
+
+        // Unlike excluded owners, the author being the only owner leaves
+        // nobody to approve those files
+        let removed = tracked.clone().remove_author(&"@a".into()).reduce();
+        assert_eq!(removed.conditional(), "@b || @c".parse()?);
+        assert_eq!(removed.no_eligible_owner, vec![tracked.clauses[0].clone()]);
+        let removed = tracked.clone().remove_author(&"@B".into()).reduce();
+        assert_eq!(removed.conditional(), "@a && @c".parse()?);
+        assert!(removed.no_eligible_owner.is_empty());
+
+        assert_eq!(tracked.unowned_files, ["unowned.txt".to_string()].into());
+        let fallback = tracked
+            .clone()
+            .with_fallback_owners(&["@leads".into(), "@org/admins".into()])
+            .reduce();
+        assert_eq!(
+            fallback.conditional(),
+            "@a && (@b || @c) && (@leads || @org/admins)".parse()?
+        );
+        assert_eq!(
+            fallback.clauses[2].provenance,
+            Provenance {
+                files: ["unowned.txt".to_string()].into(),
+                rules: [].into(),
+            }
+        );
+        assert_eq!(fallback.unowned_files, tracked.unowned_files);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sections() -> anyhow::Result<()> {
+        let codeowners = CodeownersRules::parse(
+            "* @a\n[Backend] @b\n/src/\n^[Docs]\n*.md @docs\n[Security][2]\n/src/auth/ @s1 @s2 @s3\n",
+        );
+        let files = ["README.md", "src/auth/login.rs"];
+        let tracked = TrackedConditional::from_codeowners(&codeowners, &files).reduce();
+
+        // Every required section needs its own approval
+        assert_eq!(
+            tracked.conditional(),
+            OwnersConditional::from_codeowners(&codeowners, &files).reduce()
+        );
+        assert_eq!(
+            tracked.conditional(),
+            "@a && @b && 2 of (@s1 || @s2 || @s3)".parse()?
+        );
+        assert_eq!(
+            tracked.clauses[1].provenance.rules,
+            [RuleRef {
+                line: 3,
+                pattern: "/src/".into(),
+                section: Some("Backend".into()),
+            }]
+            .into()
+        );
+
+        // Optional sections are only advisory
+        assert_eq!(
+            tracked
+                .advisory
+                .iter()
+                .map(|clause| (format!("{}", clause.conditional), clause.provenance.clone()))
+                .collect::<Vec<_>>(),
+            vec![(
+                "@docs".to_string(),
+                Provenance {
+                    files: ["README.md".to_string()].into(),
+                    rules: [RuleRef {
+                        line: 5,
+                        pattern: "*.md".into(),
+                        section: Some("Docs".into()),
+                    }]
+                    .into(),
+                }
+            )]
+        );
+        assert!(
+            tracked
+                .remove_author(&"@docs".into())
+                .reduce()
+                .advisory
+                .is_empty()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+//! Alternative ways of showing a requirement, for when the infix expression
+//! gets too nested to read.
+use crate::conditional::{Approvals, Evaluation, OwnersConditional};
+use serde::Deserialize;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderFormat {
+    /// Just the infix expression, e.g. `(@a && (@b || @c))`.
+    #[default]
+    Expression,
+    /// A Markdown task list, with approved parts ticked off.
+    Checklist,
+    /// A Mermaid flowchart, which GitHub renders inline.
+    Mermaid,
+    /// A Graphviz DOT graph.
+    Dot,
+}
+
+impl FromStr for RenderFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RenderFormat, String> {
+        match s {
+            "expression" => Ok(RenderFormat::Expression),
+            "checklist" => Ok(RenderFormat::Checklist),
+            "mermaid" => Ok(RenderFormat::Mermaid),
+            "dot" => Ok(RenderFormat::Dot),
+            other => Err(format!(
+                "unknown format `{other}`, expected one of expression, checklist, mermaid or dot"
+            )),
+        }
+    }
+}
+
+/// Renders `conditional` in the given format. Parts already satisfied by
+/// `approvals` are ticked off or highlighted.
+pub fn render(
+    conditional: &OwnersConditional,
+    format: RenderFormat,
+    approvals: &Approvals,
+) -> String {
+    match format {
+        RenderFormat::Expression => format!("`{conditional}`"),
+        RenderFormat::Checklist => render_checklist(conditional, approvals),
+        RenderFormat::Mermaid => render_mermaid(conditional, approvals),
+        RenderFormat::Dot => render_dot(conditional, approvals),
+    }
+}
+
+/// A node of the requirement tree, in the order they are visited.
+struct Node {
+    label: String,
+    parent: Option<usize>,
+    depth: usize,
+    satisfied: bool,
+}
+
+fn render_checklist(conditional: &OwnersConditional, approvals: &Approvals) -> String {
+    if conditional.conjuncts().is_empty() {
+        return "No reviewers are required".to_string();
+    }
+
+    // Every clause of the top level `And` is needed, so list them directly
+    // rather than under an "All of" item
+    let mut nodes = vec![];
+    for clause in conditional.conjuncts() {
+        collect_nodes(clause, approvals, None, 0, &mut nodes);
+    }
+    nodes
+        .iter()
+        .map(|node| {
+            let check = if node.satisfied { "x" } else { " " };
+            format!("{}- [{check}] {}", "  ".repeat(node.depth), node.label)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_mermaid(conditional: &OwnersConditional, approvals: &Approvals) -> String {
+    let mut nodes = vec![];
+    collect_nodes(conditional, approvals, None, 0, &mut nodes);
+
+    let mut lines = vec!["```mermaid".to_string(), "flowchart TD".to_string()];
+    for (idx, node) in nodes.iter().enumerate() {
+        // Mermaid doesn't render backticks inside labels, and needs quotes
+        // written as an entity
+        let label = node.label.replace('`', "").replace('"', "#quot;");
+        lines.push(format!("    n{idx}[\"{label}\"]"));
+        if let Some(parent) = node.parent {
+            lines.push(format!("    n{parent} --> n{idx}"));
+        }
+    }
+    let satisfied = satisfied_ids(&nodes);
+    if !satisfied.is_empty() {
+        lines.push("    classDef approved fill:#d4edda,stroke:#28a745".to_string());
+        lines.push(format!("    class {} approved", satisfied.join(",")));
+    }
+    lines.push("```".to_string());
+    lines.join("\n")
+}
+
+fn render_dot(conditional: &OwnersConditional, approvals: &Approvals) -> String {
+    let mut nodes = vec![];
+    collect_nodes(conditional, approvals, None, 0, &mut nodes);
+
+    let mut lines = vec![
+        "digraph requirement {".to_string(),
+        "    node [shape=box];".to_string(),
+    ];
+    for (idx, node) in nodes.iter().enumerate() {
+        let label = node
+            .label
+            .replace('`', "")
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"");
+        let style = if node.satisfied {
+            ", style=filled, fillcolor=palegreen"
+        } else {
+            ""
+        };
+        lines.push(format!("    n{idx} [label=\"{label}\"{style}];"));
+        if let Some(parent) = node.parent {
+            lines.push(format!("    n{parent} -> n{idx};"));
+        }
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Walks the requirement depth first, adding a node for every group and
+/// owner.
+fn collect_nodes(
+    conditional: &OwnersConditional,
+    approvals: &Approvals,
+    parent: Option<usize>,
+    depth: usize,
+    nodes: &mut Vec<Node>,
+) {
+    let (label, items) = match conditional {
+        OwnersConditional::And(items) if items.is_empty() => {
+            ("No reviewers required".to_string(), &items[..])
+        }
+        OwnersConditional::Or(items) if items.is_empty() => {
+            ("No owner can approve this".to_string(), &items[..])
+        }
+        OwnersConditional::And(items) => ("All of".to_string(), &items[..]),
+        OwnersConditional::Or(items) => ("Any of".to_string(), &items[..]),
+        OwnersConditional::AtLeast(count, items) => (format!("At least {count} of"), &items[..]),
+        OwnersConditional::Owner(owner) => (format!("`{owner}`"), &[][..]),
+    };
+    let idx = nodes.len();
+    nodes.push(Node {
+        label,
+        parent,
+        depth,
+        satisfied: conditional.evaluate(approvals) == Evaluation::Satisfied,
+    });
+    for item in items {
+        collect_nodes(item, approvals, Some(idx), depth + 1, nodes);
+    }
+}
+
+fn satisfied_ids(nodes: &[Node]) -> Vec<String> {
+    nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, node)| node.satisfied)
+        .map(|(idx, _)| format!("n{idx}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RenderFormat, render};
+    use crate::conditional::{Approvals, OwnersConditional};
+
+    #[test]
+    fn test_render() -> anyhow::Result<()> {
+        let conditional: OwnersConditional =
+            "@a && (@b || (@c && @org/d)) && 2 of (@e || @f || @g)"
+                .parse::<OwnersConditional>()?
+                .reduce();
+        let approvals = Approvals::from_logins(["b", "e"]);
+
+        assert_eq!(
+            render(&conditional, RenderFormat::Expression, &approvals),
+            format!("`{conditional}`")
+        );
+        assert_eq!(
+            render(&conditional, RenderFormat::Checklist, &approvals),
+            r#"- [ ] `@a`
+- [x] Any of
+  - [x] `@b`
+  - [ ] All of
+    - [ ] `@c`
+    - [ ] `@org/d`
+- [ ] At least 2 of
+  - [x] `@e`
+  - [ ] `@f`
+  - [ ] `@g`"#
+        );
+        assert_eq!(
+            render(&"@a".parse()?, RenderFormat::Mermaid, &approvals),
+            "```mermaid\nflowchart TD\n    n0[\"@a\"]\n```"
+        );
+        assert_eq!(
+            render(
+                &r#"@a || "@q\"x""#.parse()?,
+                RenderFormat::Mermaid,
+                &approvals
+            ),
+            r#"```mermaid
+flowchart TD
+    n0["Any of"]
+    n1["@a"]
+    n0 --> n1
+    n2["@q#quot;x"]
+    n0 --> n2
+```"#
+        );
+        assert_eq!(
+            render(&"@a || @b".parse()?, RenderFormat::Dot, &approvals),
+            r#"digraph requirement {
+    node [shape=box];
+    n0 [label="Any of", style=filled, fillcolor=palegreen];
+    n1 [label="@a"];
+    n0 -> n1;
+    n2 [label="@b", style=filled, fillcolor=palegreen];
+    n0 -> n2;
+}"#
+        );
+        assert_eq!(
+            render(
+                &OwnersConditional::And(vec![]),
+                RenderFormat::Checklist,
+                &approvals
+            ),
+            "No reviewers are required"
+        );
+
+        assert_eq!("mermaid".parse(), Ok(RenderFormat::Mermaid));
+        assert!("svg".parse::<RenderFormat>().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+//! A stable, machine readable summary of a PR's ownership requirements, for
+//! tooling that would otherwise have to scrape the Markdown comment.
+//!
+//! The JSON looks like this:
+//!
+//! ```json
+//! {
+//!   "schema_version": 1,
+//!   "requirement": {"and": [{"owner": "@a"}, {"or": [{"owner": "@b"}, {"owner": "@org/c"}]}]},
+//!   "files": {"src/lib.rs": ["@a"], "src/b.rs": ["@b", "@org/c"], "unowned.txt": null},
+//!   "minimum_sets": {"sets": [["@a", "@b"], ["@a", "@org/c"]], "exact": true},
+//!   "blocked_files": []
+//! }
+//! ```
+//!
+//! - `requirement` is the reduced requirement, with owners excluded by the
+//!   config removed. Each node is an object with exactly one of the keys
+//!   `and`, `or` (both lists of nodes), `at_least` (a `[count, nodes]` pair,
+//!   satisfied by approvals from `count` of the nodes) or `owner` (a string).
+//! - `files` maps every changed file to its owners as listed in CODEOWNERS, or
+//!   `null` if no rule matches the file.
+//! - `minimum_sets` lists the smallest sets of owners that satisfy the
+//!   requirement. `exact` is false if these are a heuristic approximation.
+//! - `blocked_files` lists changed files that keep the PR from being merged
+//!   however it's approved, which are the unowned files when the config
+//!   blocks them. Reports from before it was added don't have it.
+//!
+//! Fields are only ever added within a schema version. Anything that removes
+//! or changes the meaning of a field bumps `schema_version`.
+use crate::{
+    conditional::{self, MinimumOwnerSets, OwnersConditional},
+    owner::Owner,
+    rules::CodeownersRules,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Marks the start of the hidden report block in the PR comment.
+const COMMENT_BLOCK_START: &str = "<!-- min-review-bot-report";
+const COMMENT_BLOCK_END: &str = "-->";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnershipReport {
+    pub schema_version: u32,
+    pub requirement: OwnersConditional,
+    pub files: BTreeMap<String, Option<Vec<Owner>>>,
+    pub minimum_sets: MinimumOwnerSets,
+    #[serde(default)]
+    pub blocked_files: BTreeSet<String>,
+}
+
+impl OwnershipReport {
+    pub fn new(
+        codeowners: &CodeownersRules,
+        files: &[&str],
+        requirement: OwnersConditional,
+        minimum_sets: MinimumOwnerSets,
+        blocked_files: BTreeSet<String>,
+    ) -> OwnershipReport {
+        let files = conditional::to_owners_map(codeowners, files)
+            .into_iter()
+            .map(|(file, owners)| (file.to_string(), owners))
+            .collect();
+        OwnershipReport {
+            schema_version: SCHEMA_VERSION,
+            requirement,
+            files,
+            minimum_sets,
+            blocked_files,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Renders the report as an HTML comment, which GitHub hides when
+    /// displaying the PR comment but other bots can read back with
+    /// `from_comment`.
+    pub fn to_comment_block(&self) -> serde_json::Result<String> {
+        // `>` can only show up inside JSON strings, so escaping it keeps the
+        // JSON valid while making sure it can never close the HTML comment
+        let json = serde_json::to_string(self)?.replace('>', "\\u003e");
+        Ok(format!(
+            "{COMMENT_BLOCK_START}\n{json}\n{COMMENT_BLOCK_END}"
+        ))
+    }
+
+    /// Reads a report back out of a PR comment. Returns `None` if the comment
+    /// doesn't contain one.
+    pub fn from_comment(body: &str) -> Option<serde_json::Result<OwnershipReport>> {
+        let start = body.find(COMMENT_BLOCK_START)? + COMMENT_BLOCK_START.len();
+        let end = start + body[start..].find(COMMENT_BLOCK_END)?;
+        Some(serde_json::from_str(&body[start..end]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{OwnershipReport, SCHEMA_VERSION};
+    use crate::{
+        conditional::{OwnersConditional, SolveMode},
+        rules::CodeownersRules,
+    };
+
+    #[test]
+    fn test_report_json() -> anyhow::Result<()> {
+        let codeowners = CodeownersRules::parse("src/ @a\nbilling/ @b @org/c\n");
+        let files = ["src/lib.rs", "billing/-->.rs", "README.md"];
+        let requirement = OwnersConditional::from_codeowners(&codeowners, &files).reduce();
+        let minimum_sets = requirement.minimum_owner_sets(SolveMode::Auto);
+        let report = OwnershipReport::new(
+            &codeowners,
+            &files,
+            requirement,
+            minimum_sets,
+            ["README.md".to_string()].into(),
+        );
+
+        assert_eq!(
+            serde_json::to_value(&report)?,
+            serde_json::json!({
+                "schema_version": SCHEMA_VERSION,
+                "requirement": {"and": [{"owner": "@a"}, {"or": [{"owner": "@b"}, {"owner": "@org/c"}]}]},
+                "files": {"src/lib.rs": ["@a"], "billing/-->.rs": ["@b", "@org/c"], "README.md": null},
+                "minimum_sets": {"sets": [["@a", "@b"], ["@a", "@org/c"]], "exact": true},
+                "blocked_files": ["README.md"],
+            })
+        );
+
+        // Reports from before `blocked_files` was added still read back
+        let mut old = serde_json::to_value(&report)?;
+        if let Some(old) = old.as_object_mut() {
+            old.remove("blocked_files");
+        }
+        let old: OwnershipReport = serde_json::from_value(old)?;
+        assert!(old.blocked_files.is_empty());
+
+        let comment = format!("# File Owners\nSome text\n{}\n", report.to_comment_block()?);
+        assert_eq!(comment.matches("-->").count(), 1);
+        assert_eq!(
+            OwnershipReport::from_comment(&comment).transpose()?,
+            Some(report)
+        );
+        assert!(OwnershipReport::from_comment("# File Owners").is_none());
+
+        Ok(())
+    }
+}
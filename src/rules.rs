@@ -0,0 +1,248 @@
+//! Line by line view of a CODEOWNERS file. We keep each rule alongside its line
+//! number and pattern to be able to explain where ownership comes from.
+use crate::{
+    codeowners::{self, Diagnostic, Section},
+    owner::Owner,
+};
+use serde::{Deserialize, Deserializer};
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub struct Rule {
+    /// 1-indexed line of the CODEOWNERS file this rule is on.
+    pub line: usize,
+    pub pattern: String,
+    pub owners: Vec<Owner>,
+    /// Index into `CodeownersRules::sections`, or `None` for rules before the
+    /// first section.
+    pub section: Option<usize>,
+    matcher: codeowners::Pattern,
+}
+
+/// What a single rule requires of a file.
+#[derive(Debug, Clone, Copy)]
+pub struct FileRequirement<'a> {
+    pub rule: &'a Rule,
+    /// The section the rule is in, if the file has sections.
+    pub section: Option<&'a Section>,
+    /// How many different owners need to approve.
+    pub approvals: usize,
+}
+
+impl FileRequirement<'_> {
+    /// Reviews for optional sections are advisory.
+    pub fn is_optional(&self) -> bool {
+        self.section.is_some_and(|section| section.optional)
+    }
+}
+
+impl Rule {
+    pub fn matches(&self, path: &str) -> bool {
+        self.matcher.matches(path)
+    }
+}
+
+/// Number of approvals needed from the owners of any file matching `path`, a
+/// pattern written the same way as in CODEOWNERS, such as `/security/`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApprovalCount {
+    #[serde(deserialize_with = "deserialize_pattern")]
+    pub path: codeowners::Pattern,
+    pub count: usize,
+}
+
+impl ApprovalCount {
+    pub fn matches(&self, path: &str) -> bool {
+        self.path.matches(path)
+    }
+}
+
+/// Parses `<pattern>=<count>`, e.g. `/security/=2`.
+impl FromStr for ApprovalCount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, count) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("expected <pattern>=<count>, got {s:?}"))?;
+        Ok(ApprovalCount {
+            path: codeowners::Pattern::new(path).map_err(|e| e.to_string())?,
+            count: count.parse().map_err(|e| format!("invalid count: {e}"))?,
+        })
+    }
+}
+
+fn deserialize_pattern<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<codeowners::Pattern, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    codeowners::Pattern::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+#[derive(Debug, Default)]
+pub struct CodeownersRules {
+    rules: Vec<Rule>,
+    sections: Vec<Section>,
+    approval_counts: Vec<ApprovalCount>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl CodeownersRules {
+    /// Lines GitHub can't parse are skipped, and can be found in
+    /// `diagnostics()`.
+    pub fn parse(content: &str) -> CodeownersRules {
+        let file = codeowners::parse(content);
+        let rules = file
+            .rules
+            .into_iter()
+            .map(|rule| Rule {
+                line: rule.span.line,
+                pattern: rule.pattern.as_str().to_string(),
+                owners: rule.owners,
+                section: rule.section,
+                matcher: rule.pattern,
+            })
+            .collect();
+        CodeownersRules {
+            rules,
+            sections: file.sections,
+            approval_counts: vec![],
+            diagnostics: file.diagnostics,
+        }
+    }
+
+    /// Requires more than one approval for the files matching each of
+    /// `approval_counts`. As with CODEOWNERS, the last match wins.
+    pub fn with_approval_counts(mut self, approval_counts: Vec<ApprovalCount>) -> CodeownersRules {
+        self.approval_counts = approval_counts;
+        self
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Empty unless the file uses GitLab style sections.
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    /// Problems with the lines that were skipped.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// The rule that decides who owns `path` on GitHub, which is the last rule
+    /// in the file that matches. Files with sections can have more than one,
+    /// see `matching_rules`.
+    pub fn matching_rule(&self, path: &str) -> Option<&Rule> {
+        self.rules.iter().rev().find(|rule| rule.matches(path))
+    }
+
+    /// The rules that decide who owns `path`: the last matching rule of each
+    /// section, in the order they appear in the file.
+    pub fn matching_rules(&self, path: &str) -> Vec<&Rule> {
+        if self.sections.is_empty() {
+            return self.matching_rule(path).into_iter().collect();
+        }
+        // Rules before the first section go in slot 0
+        let mut decided = vec![false; self.sections.len() + 1];
+        let mut rules = vec![];
+        for rule in self.rules.iter().rev() {
+            let slot = rule.section.map_or(0, |section| section + 1);
+            if !decided[slot] && rule.matches(path) {
+                decided[slot] = true;
+                rules.push(rule);
+            }
+        }
+        rules.reverse();
+        rules
+    }
+
+    /// Everyone listed as an owner of `path`, across all sections. `None` if
+    /// no rule matches it.
+    pub fn owners_of(&self, path: &str) -> Option<Vec<Owner>> {
+        let rules = self.matching_rules(path);
+        if rules.is_empty() {
+            return None;
+        }
+        let mut owners: Vec<Owner> = vec![];
+        for owner in rules.iter().flat_map(|rule| &rule.owners) {
+            if !owners.contains(owner) {
+                owners.push(owner.clone());
+            }
+        }
+        Some(owners)
+    }
+
+    /// What each of the rules deciding who owns `path` requires. A section's
+    /// approval count only ever raises the one from `with_approval_counts`.
+    pub fn requirements_of(&self, path: &str) -> Vec<FileRequirement<'_>> {
+        let approvals = self.required_approvals(path);
+        self.matching_rules(path)
+            .into_iter()
+            .map(|rule| {
+                let section = rule.section.map(|section| &self.sections[section]);
+                FileRequirement {
+                    rule,
+                    section,
+                    approvals: section
+                        .map_or(approvals, |section| section.approvals.max(approvals)),
+                }
+            })
+            .collect()
+    }
+
+    /// How many different owners need to approve changes to `path`.
+    pub fn required_approvals(&self, path: &str) -> usize {
+        self.approval_counts
+            .iter()
+            .rev()
+            .find(|approval_count| approval_count.matches(path))
+            .map_or(1, |approval_count| approval_count.count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ApprovalCount, CodeownersRules};
+
+    #[test]
+    fn test_matching_rule() {
+        let codeowners = CodeownersRules::parse(
+            "* @all\n*.md @docs\n/src/ @src\nsrc/billing/ @billing\n/SRC/[Bb]ar.rs @bar\n/src/gen/\n",
+        );
+        let owner_of = |path| {
+            codeowners
+                .matching_rule(path)
+                .and_then(|rule| rule.owners.first())
+                .map(|owner| owner.to_string())
+        };
+        assert_eq!(owner_of("README.md"), Some("@docs".into()));
+        assert_eq!(owner_of("src/lib.rs"), Some("@src".into()));
+        assert_eq!(owner_of("src/billing/a/b.rs"), Some("@billing".into()));
+        // A slash in the middle ties the pattern to the root, as in .gitignore
+        assert_eq!(owner_of("lib/src/billing/c.rs"), Some("@all".into()));
+        // Paths are case sensitive, and GitHub skips lines with `[ ]` ranges
+        assert_eq!(owner_of("SRC/Bar.rs"), Some("@all".into()));
+        assert_eq!(owner_of("src/gen/a.rs"), None);
+        assert_eq!(owner_of("Cargo.toml"), Some("@all".into()));
+        assert_eq!(codeowners.diagnostics()[0].span.line, 5);
+    }
+
+    #[test]
+    fn test_approval_count() -> anyhow::Result<()> {
+        let parse = |s: &str| s.parse::<ApprovalCount>().map_err(anyhow::Error::msg);
+        // Patterns mean the same as they do in CODEOWNERS
+        let security = parse("/security/=2")?;
+        assert_eq!(security.count, 2);
+        assert!(security.matches("security/auth/login.rs"));
+        assert!(!security.matches("lib/security/auth.rs"));
+        assert!(parse("*.md=2")?.matches("docs/guide/README.md"));
+        assert!(!parse("docs/*=2")?.matches("docs/guide/README.md"));
+
+        assert!(parse("security/").is_err());
+        assert!(parse("[Ss]ecurity/=2").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+//! Explains who owns a path: every CODEOWNERS rule that matches it, and which
+//! of them decide its owners.
+use crate::{owner::Owner, rules::CodeownersRules};
+use std::fmt::{self, Display};
+
+/// A CODEOWNERS rule that matches the path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleMatch {
+    /// 1-indexed line of the CODEOWNERS file.
+    pub line: usize,
+    pub pattern: String,
+    pub owners: Vec<Owner>,
+    /// Name of the section the rule is in, if the file has sections.
+    pub section: Option<String>,
+    /// The later rule that decides the owners instead, or `None` if this rule
+    /// does.
+    pub overridden_by: Option<usize>,
+}
+
+impl RuleMatch {
+    pub fn wins(&self) -> bool {
+        self.overridden_by.is_none()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathOwners {
+    pub path: String,
+    /// Everyone listed by the winning rules. Empty if the path is unowned.
+    pub owners: Vec<Owner>,
+    /// Every rule that matches the path, in the order they appear in the file.
+    pub matches: Vec<RuleMatch>,
+}
+
+/// Works out who owns `path` and why. A leading `./` or `/` is ignored, since
+/// patterns are matched against paths relative to the root of the repo.
+pub fn who_owns(codeowners: &CodeownersRules, path: &str) -> PathOwners {
+    let path = path.trim_start_matches("./").trim_start_matches('/');
+    let winners = codeowners.matching_rules(path);
+    let matches = codeowners
+        .rules()
+        .iter()
+        .filter(|rule| rule.matches(path))
+        .map(|rule| RuleMatch {
+            line: rule.line,
+            pattern: rule.pattern.clone(),
+            owners: rule.owners.clone(),
+            section: rule
+                .section
+                .map(|section| codeowners.sections()[section].name.clone()),
+            overridden_by: winners
+                .iter()
+                .find(|winner| winner.section == rule.section)
+                .map(|winner| winner.line)
+                .filter(|&line| line != rule.line),
+        })
+        .collect();
+    PathOwners {
+        path: path.to_string(),
+        owners: codeowners.owners_of(path).unwrap_or_default(),
+        matches,
+    }
+}
+
+impl Display for PathOwners {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.owners.is_empty() {
+            writeln!(f, "{}: no owners", self.path)?;
+        } else {
+            writeln!(f, "{}: {}", self.path, join_owners(&self.owners))?;
+        }
+        if self.matches.is_empty() {
+            return writeln!(f, "  no rule matches");
+        }
+        for rule in &self.matches {
+            write!(f, "  line {}: `{}`", rule.line, rule.pattern)?;
+            if let Some(section) = &rule.section {
+                write!(f, " in section `{section}`")?;
+            }
+            if rule.owners.is_empty() {
+                write!(f, " (no owners)")?;
+            } else {
+                write!(f, " {}", join_owners(&rule.owners))?;
+            }
+            match rule.overridden_by {
+                Some(line) => writeln!(f, ", overridden by line {line}")?,
+                None => writeln!(f, ", wins")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn join_owners(owners: &[Owner]) -> String {
+    owners
+        .iter()
+        .map(|owner| owner.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RuleMatch, who_owns};
+    use crate::rules::CodeownersRules;
+
+    #[test]
+    fn test_who_owns() {
+        let codeowners = CodeownersRules::parse(
+            "* @all\n/src/ @src\n/src/gen/\n*.rs @rust\n[Security][2] @org/security\n/src/auth/\n",
+        );
+
+        let owners = who_owns(&codeowners, "./src/lib.rs");
+        assert_eq!(owners.path, "src/lib.rs");
+        assert_eq!(owners.owners, vec!["@rust".into()]);
+        assert_eq!(
+            owners
+                .matches
+                .iter()
+                .map(RuleMatch::wins)
+                .collect::<Vec<_>>(),
+            vec![false, false, true]
+        );
+        assert_eq!(
+            owners.to_string(),
+            "src/lib.rs: @rust\n  \
+             line 1: `*` @all, overridden by line 4\n  \
+             line 2: `/src/` @src, overridden by line 4\n  \
+             line 4: `*.rs` @rust, wins\n"
+        );
+
+        // Each section has its own winner
+        assert_eq!(
+            who_owns(&codeowners, "src/auth/login.rs").to_string(),
+            "src/auth/login.rs: @rust @org/security\n  \
+             line 1: `*` @all, overridden by line 4\n  \
+             line 2: `/src/` @src, overridden by line 4\n  \
+             line 4: `*.rs` @rust, wins\n  \
+             line 6: `/src/auth/` in section `Security` @org/security, wins\n"
+        );
+
+        assert_eq!(
+            who_owns(&codeowners, "src/gen/schema.json").to_string(),
+            "src/gen/schema.json: no owners\n  \
+             line 1: `*` @all, overridden by line 3\n  \
+             line 2: `/src/` @src, overridden by line 3\n  \
+             line 3: `/src/gen/` (no owners), wins\n"
+        );
+        assert_eq!(
+            who_owns(&CodeownersRules::parse("/src/ @src\n"), "README.md").to_string(),
+            "README.md: no owners\n  no rule matches\n"
+        );
+    }
+}